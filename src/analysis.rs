@@ -0,0 +1,809 @@
+//! Derived metrics computed from raw API records.
+
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, FixedOffset, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::models::{
+    DailyCardiovascularAge, DailyReadiness, DailyResilience, DailySleep, DailySpo2, Dated,
+    EnhancedTag, HeartRateSample, RestModePeriod, Session, Sleep, SleepPhase, SleepTime, Tag,
+    TimeSeries, Vo2Max, Workout,
+};
+
+/// A run of consecutive awake 5-minute intervals during a sleep period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WakeEpisode {
+    /// When the run began, in the sleep period's local offset.
+    pub start: DateTime<FixedOffset>,
+    pub minutes: i64,
+}
+
+/// Splits range results into per-day groups, keyed by `YYYY-MM-DD`.
+pub fn group_by_day<T: Dated>(records: Vec<T>) -> HashMap<String, Vec<T>> {
+    let mut groups: HashMap<String, Vec<T>> = HashMap::new();
+    for record in records {
+        groups
+            .entry(record.day().into_owned())
+            .or_default()
+            .push(record);
+    }
+    groups
+}
+
+/// Picks the main night's sleep from a day's periods, falling back to the
+/// first period (e.g. a nap) when there's no `long_sleep`. Takes owned or
+/// borrowed periods.
+pub fn primary_sleep<S: Borrow<Sleep>>(records: &[S]) -> Option<&Sleep> {
+    records
+        .iter()
+        .map(Borrow::borrow)
+        .find(|s| s.sleep_type.as_deref() == Some("long_sleep"))
+        .or(records.first().map(Borrow::borrow))
+}
+
+/// Awake runs of 10 minutes or longer from `sleep_phase_5_min`. Shorter blips
+/// are normal and not worth flagging.
+pub fn wake_episodes(sleep: &Sleep) -> Vec<WakeEpisode> {
+    let mut runs = Vec::new();
+    // Start and end of the awake run in progress.
+    let mut run: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> = None;
+
+    for (at, phase) in sleep.phases() {
+        if phase == SleepPhase::Awake {
+            let end = at + PHASE_INTERVAL;
+            run = Some(run.map_or((at, end), |(start, _)| (start, end)));
+        } else if let Some((start, end)) = run.take() {
+            runs.extend(wake_run(start, end));
+        }
+    }
+    runs.extend(run.and_then(|(start, end)| wake_run(start, end)));
+
+    runs
+}
+
+/// Length of one `sleep_phase_5_min` interval.
+const PHASE_INTERVAL: Duration = Duration::minutes(5);
+
+fn wake_run(start: DateTime<FixedOffset>, end: DateTime<FixedOffset>) -> Option<WakeEpisode> {
+    let minutes = (end - start).num_minutes();
+    (minutes >= 10).then_some(WakeEpisode { start, minutes })
+}
+
+/// How a finding should be read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// Worth knowing, not necessarily a problem (e.g. a wake episode).
+    #[default]
+    Notice,
+    /// Something dragged the night down.
+    Issue,
+    /// Better than the personal baseline.
+    Positive,
+}
+
+/// One finding from `analyze`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Flag {
+    /// Stable identifier, e.g. `wake`, `low_deep_sleep`, `temp_elevated`.
+    pub kind: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    /// The measurement behind the flag, in the unit its kind implies
+    /// (minutes for wakes, seconds for durations, % for efficiency, °C for temperature).
+    pub value: Option<f64>,
+    /// Local clock time the finding refers to (`HH:MM`), when it has one.
+    pub at: Option<String>,
+    /// Tagged events that may explain the finding, e.g. "alcohol tagged at 21:40".
+    pub notes: Vec<String>,
+}
+
+/// The night's findings, in the order they're reported.
+#[derive(Debug, Clone, Serialize)]
+pub struct Analysis {
+    pub day: Option<String>,
+    pub sleep_score: Option<i64>,
+    pub bedtime_start: Option<String>,
+    pub bedtime_end: Option<String>,
+    /// False when there was no detailed sleep period to analyze.
+    pub has_sleep: bool,
+    pub flags: Vec<Flag>,
+    /// Tags from the evening before through the end of the night.
+    pub tags: Vec<TagEvent>,
+}
+
+impl Analysis {
+    /// No notices or issues (positive findings don't count).
+    pub fn is_clean(&self) -> bool {
+        self.flags.iter().all(|f| f.severity == Severity::Positive)
+    }
+}
+
+impl Flag {
+    fn new(kind: &'static str, severity: Severity, message: String, value: f64) -> Self {
+        Self {
+            kind,
+            severity,
+            message,
+            value: Some(value),
+            at: None,
+            notes: Vec::new(),
+        }
+    }
+}
+
+/// A tag from either the `tag` or `enhanced_tag` endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TagEvent {
+    pub day: String,
+    /// Readable name, e.g. `alcohol`, `late meal`, or the custom tag's name.
+    pub name: String,
+    pub start: String,
+    /// Set for spans such as travel or sickness.
+    pub end: Option<String>,
+    pub comment: Option<String>,
+}
+
+impl TagEvent {
+    /// "alcohol tagged at 21:40"
+    pub fn note(&self) -> String {
+        match self.start.get(11..16) {
+            Some(time) => format!("{} tagged at {time}", self.name),
+            None => format!("{} tagged", self.name),
+        }
+    }
+}
+
+/// `tag_generic_late_meal` → `late meal`.
+fn tag_name(code: &str) -> String {
+    code.strip_prefix("tag_generic_")
+        .or_else(|| code.strip_prefix("tag_"))
+        .unwrap_or(code)
+        .replace('_', " ")
+}
+
+/// Both tag formats as one list, oldest first. An old-style entry with
+/// several tags becomes one event per tag.
+pub fn tag_events(tags: &[Tag], enhanced: &[EnhancedTag]) -> Vec<TagEvent> {
+    let mut events: Vec<TagEvent> = tags
+        .iter()
+        .flat_map(|tag| {
+            tag.tags.iter().map(|code| TagEvent {
+                day: tag.day.clone(),
+                name: tag_name(code),
+                start: tag.timestamp.clone(),
+                end: None,
+                comment: tag.text.clone(),
+            })
+        })
+        .collect();
+    events.extend(enhanced.iter().map(|tag| {
+        TagEvent {
+            day: tag.start_day.clone(),
+            name: tag
+                .custom_name
+                .clone()
+                .or_else(|| tag.tag_type_code.as_deref().map(tag_name))
+                .unwrap_or_else(|| "tag".to_string()),
+            start: tag.start_time.clone(),
+            end: tag.end_time.clone(),
+            comment: tag.comment.clone(),
+        }
+    }));
+    events.sort_by(|a, b| a.start.cmp(&b.start));
+    events
+}
+
+/// How far before bedtime a tag can still affect the night.
+const TAG_LOOKBACK_HOURS: i64 = 12;
+
+/// Limits outside which `analyze` flags a measurement, and the score bands
+/// used to color scores.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thresholds {
+    /// Scores at or above this are good (green).
+    pub good_score: i64,
+    /// Scores at or above this, but below `good_score`, are fair (yellow).
+    pub fair_score: i64,
+    /// Less deep sleep than this many minutes is flagged.
+    pub min_deep_sleep_minutes: i64,
+    /// Less REM sleep than this many minutes is flagged.
+    pub min_rem_sleep_minutes: i64,
+    /// Sleep efficiency below this (percent) is flagged.
+    pub min_efficiency: i64,
+    /// More restless periods than this is flagged.
+    pub max_restless_periods: i64,
+    /// A body temperature deviation this far from baseline (°C, either way) is flagged.
+    pub max_temp_deviation: f64,
+    /// Average overnight SpO2 below this (percent) is flagged.
+    pub min_spo2: f64,
+    /// Breathing disturbances per hour above this are flagged.
+    pub max_bdi: i64,
+    /// A sleep score this many points above or below your baseline is flagged.
+    pub baseline_delta: i64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            good_score: 85,
+            fair_score: 70,
+            min_deep_sleep_minutes: 60,
+            min_rem_sleep_minutes: 60,
+            min_efficiency: 80,
+            max_restless_periods: 20,
+            max_temp_deviation: 0.5,
+            min_spo2: 95.0,
+            max_bdi: 15,
+            baseline_delta: 5,
+        }
+    }
+}
+
+/// Flags that tags like alcohol, caffeine or a late meal can plausibly explain.
+const TAG_SENSITIVE_FLAGS: [&str; 8] = [
+    "low_deep_sleep",
+    "low_rem",
+    "low_efficiency",
+    "high_restlessness",
+    "temp_elevated",
+    "low_spo2",
+    "high_bdi",
+    "below_baseline",
+];
+
+/// Tags overlapping the `TAG_LOOKBACK_HOURS` before bedtime through wake-up.
+fn tags_for_night(sleep: &Sleep, tags: &[TagEvent]) -> Vec<TagEvent> {
+    let parse = |s: &str| DateTime::parse_from_rfc3339(s).ok();
+    let (Some(bedtime), Some(wake)) = (
+        sleep.bedtime_start.as_deref().and_then(parse),
+        sleep.bedtime_end.as_deref().and_then(parse),
+    ) else {
+        return Vec::new();
+    };
+    let from = bedtime - Duration::hours(TAG_LOOKBACK_HOURS);
+
+    tags.iter()
+        .filter(|tag| {
+            let Some(start) = parse(&tag.start) else {
+                return false;
+            };
+            let end = tag.end.as_deref().and_then(parse).unwrap_or(start);
+            start <= wake && end >= from
+        })
+        .cloned()
+        .collect()
+}
+
+/// Flags wake episodes, short deep/REM sleep, low efficiency, restlessness,
+/// temperature deviation, SpO2 and breathing disturbances outside
+/// `thresholds`, and the score delta against the personal baseline.
+/// `tags` covering the evening before are attached as notes to the flags
+/// they could explain.
+pub fn analyze(
+    daily_sleep: Option<&DailySleep>,
+    daily_readiness: Option<&DailyReadiness>,
+    daily_spo2: Option<&DailySpo2>,
+    records: &[Sleep],
+    tags: &[TagEvent],
+    thresholds: &Thresholds,
+) -> Analysis {
+    let sleep = primary_sleep(records);
+    let day = daily_sleep
+        .map(|d| d.day.clone())
+        .or_else(|| daily_readiness.map(|r| r.day.clone()))
+        .or_else(|| sleep.map(|s| s.day.clone()));
+
+    let mut analysis = Analysis {
+        day,
+        sleep_score: daily_sleep.and_then(|d| d.score),
+        bedtime_start: sleep.and_then(|s| s.bedtime_start.clone()),
+        bedtime_end: sleep.and_then(|s| s.bedtime_end.clone()),
+        has_sleep: sleep.is_some(),
+        flags: Vec::new(),
+        tags: Vec::new(),
+    };
+    let Some(s) = sleep else {
+        return analysis;
+    };
+    let flags = &mut analysis.flags;
+
+    let wake_runs = wake_episodes(s);
+    for wake in &wake_runs {
+        let time = wake.start.format("%H:%M").to_string();
+        flags.push(Flag {
+            at: Some(time.clone()),
+            ..Flag::new(
+                "wake",
+                Severity::Notice,
+                format!("Woke at {time} for {} min", wake.minutes),
+                wake.minutes as f64,
+            )
+        });
+    }
+    if let Some(longest) = wake_runs.iter().max_by_key(|wake| wake.minutes) {
+        let time = longest.start.format("%H:%M").to_string();
+        flags.push(Flag {
+            at: Some(time.clone()),
+            ..Flag::new(
+                "longest_wake",
+                Severity::Notice,
+                format!("Longest wake: {time} ({} min)", longest.minutes),
+                longest.minutes as f64,
+            )
+        });
+    }
+
+    if let Some(deep) = s
+        .deep_sleep_duration
+        .filter(|deep| *deep < thresholds.min_deep_sleep_minutes * 60)
+    {
+        flags.push(Flag::new(
+            "low_deep_sleep",
+            Severity::Issue,
+            format!("Low deep sleep: {}", format_duration(deep)),
+            deep as f64,
+        ));
+    }
+    if let Some(rem) = s
+        .rem_sleep_duration
+        .filter(|rem| *rem < thresholds.min_rem_sleep_minutes * 60)
+    {
+        flags.push(Flag::new(
+            "low_rem",
+            Severity::Issue,
+            format!("Low REM: {}", format_duration(rem)),
+            rem as f64,
+        ));
+    }
+    if let Some(efficiency) = s.efficiency.filter(|eff| *eff < thresholds.min_efficiency) {
+        flags.push(Flag::new(
+            "low_efficiency",
+            Severity::Issue,
+            format!("Low efficiency: {efficiency}%"),
+            efficiency as f64,
+        ));
+    }
+    if let Some(restless) = s
+        .restless_periods
+        .filter(|periods| *periods > thresholds.max_restless_periods)
+    {
+        flags.push(Flag::new(
+            "high_restlessness",
+            Severity::Issue,
+            format!("High restlessness: {restless} periods"),
+            restless as f64,
+        ));
+    }
+
+    if let Some(temp) = daily_readiness.and_then(|r| r.temperature_deviation) {
+        if temp >= thresholds.max_temp_deviation {
+            flags.push(Flag::new(
+                "temp_elevated",
+                Severity::Issue,
+                format!("Temp elevated: {temp:+.1}°C — possible illness or stress"),
+                temp,
+            ));
+        } else if temp <= -thresholds.max_temp_deviation {
+            flags.push(Flag::new(
+                "temp_depressed",
+                Severity::Issue,
+                format!("Temp depressed: {temp:+.1}°C"),
+                temp,
+            ));
+        }
+    }
+
+    if let Some(spo2) = daily_spo2
+        .and_then(DailySpo2::average)
+        .filter(|spo2| *spo2 < thresholds.min_spo2)
+    {
+        flags.push(Flag::new(
+            "low_spo2",
+            Severity::Issue,
+            format!("Low SpO2: {spo2:.1}%"),
+            spo2,
+        ));
+    }
+    if let Some(bdi) = daily_spo2
+        .and_then(|d| d.breathing_disturbance_index)
+        .filter(|bdi| *bdi > thresholds.max_bdi)
+    {
+        flags.push(Flag::new(
+            "high_bdi",
+            Severity::Issue,
+            format!("Breathing disturbances: {bdi}/h"),
+            bdi as f64,
+        ));
+    }
+
+    if let Some(delta) = s.sleep_score_delta {
+        if delta > thresholds.baseline_delta {
+            flags.push(Flag::new(
+                "above_baseline",
+                Severity::Positive,
+                format!("Better than your baseline (+{delta} pts)"),
+                delta as f64,
+            ));
+        } else if delta < -thresholds.baseline_delta {
+            flags.push(Flag::new(
+                "below_baseline",
+                Severity::Issue,
+                format!("Below your baseline ({delta} pts)"),
+                delta as f64,
+            ));
+        }
+    }
+
+    analysis.tags = tags_for_night(s, tags);
+    let notes: Vec<String> = analysis.tags.iter().map(TagEvent::note).collect();
+    for flag in &mut analysis.flags {
+        if TAG_SENSITIVE_FLAGS.contains(&flag.kind) {
+            flag.notes = notes.clone();
+        }
+    }
+
+    analysis
+}
+
+/// Sources reported first, in this order; any others follow alphabetically.
+const HEART_RATE_SOURCES: [&str; 4] = ["awake", "rest", "sleep", "workout"];
+
+/// BPM range of the samples from one source.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeartRateStats {
+    pub source: String,
+    pub samples: usize,
+    pub min: i64,
+    pub max: i64,
+    pub average: f64,
+}
+
+/// Min/max/average BPM per `source`.
+pub fn heart_rate_by_source(samples: &[HeartRateSample]) -> Vec<HeartRateStats> {
+    let mut by_source: BTreeMap<&str, Vec<i64>> = BTreeMap::new();
+    for sample in samples {
+        by_source
+            .entry(&sample.source)
+            .or_default()
+            .push(sample.bpm);
+    }
+
+    let mut stats: Vec<_> = by_source
+        .into_iter()
+        .map(|(source, bpm)| HeartRateStats {
+            source: source.to_string(),
+            samples: bpm.len(),
+            min: bpm.iter().copied().min().unwrap_or_default(),
+            max: bpm.iter().copied().max().unwrap_or_default(),
+            average: bpm.iter().sum::<i64>() as f64 / bpm.len() as f64,
+        })
+        .collect();
+    // Stable, so the rest keep their alphabetical order.
+    stats.sort_by_key(|s| {
+        HEART_RATE_SOURCES
+            .iter()
+            .position(|known| *known == s.source)
+            .unwrap_or(HEART_RATE_SOURCES.len())
+    });
+    stats
+}
+
+/// Average BPM in consecutive `minutes`-wide slots from the first sample to
+/// the last, so irregular readings line up on a fixed grid. Slots without a
+/// sample are `None`. Returns the first slot's start alongside.
+pub fn bpm_slots(
+    samples: &[HeartRateSample],
+    minutes: i64,
+) -> Option<(DateTime<FixedOffset>, Vec<Option<f64>>)> {
+    let mut readings: Vec<_> = samples
+        .iter()
+        .filter_map(|s| Some((DateTime::parse_from_rfc3339(&s.timestamp).ok()?, s.bpm)))
+        .collect();
+    readings.sort_by_key(|(at, _)| *at);
+    let start = readings.first()?.0;
+    let last = readings.last()?.0;
+
+    let slot = |at: DateTime<FixedOffset>| ((at - start).num_minutes() / minutes) as usize;
+    let mut sums = vec![(0i64, 0usize); slot(last) + 1];
+    for (at, bpm) in readings {
+        let (sum, count) = &mut sums[slot(at)];
+        *sum += bpm;
+        *count += 1;
+    }
+    let slots = sums
+        .into_iter()
+        .map(|(sum, count)| (count > 0).then(|| sum as f64 / count as f64))
+        .collect();
+    Some((start, slots))
+}
+
+/// A measurement and the day it was taken.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reading<T> {
+    pub day: String,
+    pub value: T,
+}
+
+/// The latest resilience level, with its contributors (0–100).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Resilience {
+    pub day: String,
+    pub level: Option<String>,
+    pub sleep_recovery: Option<f64>,
+    pub daytime_recovery: Option<f64>,
+    pub stress: Option<f64>,
+}
+
+/// VO2 max readings over the window, oldest first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Vo2MaxTrend {
+    pub latest: Option<Reading<f64>>,
+    /// Latest minus the earliest reading in the window.
+    pub change: Option<f64>,
+    pub history: Vec<Reading<f64>>,
+}
+
+/// Cardiovascular age, resilience and VO2 max as of `day`. Each uses the most
+/// recent reading in the window, since none of them is updated daily.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Fitness {
+    pub day: String,
+    pub vascular_age: Option<Reading<i64>>,
+    pub resilience: Option<Resilience>,
+    pub vo2_max: Vo2MaxTrend,
+}
+
+/// Summarizes the records fetched for a window ending on `day`.
+pub fn fitness(
+    day: &str,
+    cardiovascular_age: &[DailyCardiovascularAge],
+    resilience: &[DailyResilience],
+    vo2_max: &[Vo2Max],
+) -> Fitness {
+    let vascular_age = cardiovascular_age
+        .iter()
+        .filter(|c| c.day.as_str() <= day)
+        .filter_map(|c| {
+            Some(Reading {
+                day: c.day.clone(),
+                value: c.vascular_age?,
+            })
+        })
+        .max_by(|a, b| a.day.cmp(&b.day));
+
+    let resilience = resilience
+        .iter()
+        .filter(|r| r.day.as_str() <= day && r.level.is_some())
+        .max_by(|a, b| a.day.cmp(&b.day))
+        .map(|r| {
+            let contributors = r.contributors.as_ref();
+            Resilience {
+                day: r.day.clone(),
+                level: r.level.clone(),
+                sleep_recovery: contributors.and_then(|c| c.sleep_recovery),
+                daytime_recovery: contributors.and_then(|c| c.daytime_recovery),
+                stress: contributors.and_then(|c| c.stress),
+            }
+        });
+
+    let mut history: Vec<Reading<f64>> = vo2_max
+        .iter()
+        .filter(|v| v.day.as_str() <= day)
+        .filter_map(|v| {
+            Some(Reading {
+                day: v.day.clone(),
+                value: v.vo2_max?,
+            })
+        })
+        .collect();
+    history.sort_by(|a, b| a.day.cmp(&b.day));
+    let latest = history.last().cloned();
+    let change = match (history.first(), history.last()) {
+        (Some(first), Some(last)) if history.len() > 1 => Some(last.value - first.value),
+        _ => None,
+    };
+
+    Fitness {
+        day: day.to_string(),
+        vascular_age,
+        resilience,
+        vo2_max: Vo2MaxTrend {
+            latest,
+            change,
+            history,
+        },
+    }
+}
+
+/// Whether `day` (`YYYY-MM-DD`) falls inside any of the Rest Mode periods.
+/// A period without an end day is still active.
+pub fn in_rest_mode(periods: &[RestModePeriod], day: &str) -> bool {
+    periods
+        .iter()
+        .any(|p| p.start_day.as_str() <= day && p.end_day.as_deref().is_none_or(|end| day <= end))
+}
+
+/// One night's actual bedtime against the recommended window.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct BedtimeNight {
+    pub day: String,
+    pub bedtime_start: String,
+    /// Minutes before (negative) or after (positive) the window; 0 inside it.
+    pub minutes_off: Option<i64>,
+}
+
+/// The recommended bedtime window (`HH:MM`) and how recent nights compare.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bedtime {
+    pub day: String,
+    pub window_start: Option<String>,
+    pub window_end: Option<String>,
+    pub status: Option<String>,
+    pub recommendation: Option<String>,
+    pub nights: Vec<BedtimeNight>,
+}
+
+const DAY_SECONDS: i64 = 24 * 60 * 60;
+
+/// Seconds from midnight as `HH:MM`; negative offsets are the evening before.
+fn clock_time(offset: i64) -> String {
+    let seconds = offset.rem_euclid(DAY_SECONDS);
+    format!("{:02}:{:02}", seconds / 3600, seconds % 3600 / 60)
+}
+
+/// `bedtime_start` as seconds from midnight in the window's timezone, counting
+/// afternoon and evening times as negative like the API's offsets do.
+fn bedtime_offset(bedtime_start: &str, day_tz: i64) -> Option<i64> {
+    let tz = FixedOffset::east_opt(i32::try_from(day_tz).ok()?)?;
+    let local = DateTime::parse_from_rfc3339(bedtime_start)
+        .ok()?
+        .with_timezone(&tz);
+    let seconds = i64::from(local.num_seconds_from_midnight());
+    Some(if local.hour() >= 12 {
+        seconds - DAY_SECONDS
+    } else {
+        seconds
+    })
+}
+
+/// Compares each day's main sleep to the latest recommendation on or before `day`.
+pub fn bedtime(day: &str, sleep_times: &[SleepTime], sleeps: &[Sleep]) -> Bedtime {
+    let recommendation = sleep_times
+        .iter()
+        .filter(|s| s.day.as_str() <= day)
+        .max_by(|a, b| a.day.cmp(&b.day));
+    let window = recommendation.and_then(|r| r.optimal_bedtime.as_ref());
+
+    let by_day: BTreeMap<&str, Vec<&Sleep>> =
+        sleeps.iter().fold(BTreeMap::new(), |mut groups, sleep| {
+            groups.entry(sleep.day.as_str()).or_default().push(sleep);
+            groups
+        });
+    let nights = by_day
+        .into_iter()
+        .filter_map(|(day, periods)| {
+            let main = primary_sleep(&periods)?;
+            let bedtime_start = main.bedtime_start.clone()?;
+            let minutes_off = window.and_then(|w| {
+                let offset = bedtime_offset(&bedtime_start, w.day_tz)?;
+                Some(if offset < w.start_offset {
+                    (offset - w.start_offset) / 60
+                } else if offset > w.end_offset {
+                    (offset - w.end_offset) / 60
+                } else {
+                    0
+                })
+            });
+            Some(BedtimeNight {
+                day: day.to_string(),
+                bedtime_start,
+                minutes_off,
+            })
+        })
+        .collect();
+
+    Bedtime {
+        day: day.to_string(),
+        window_start: window.map(|w| clock_time(w.start_offset)),
+        window_end: window.map(|w| clock_time(w.end_offset)),
+        status: recommendation.and_then(|r| r.status.clone()),
+        recommendation: recommendation.and_then(|r| r.recommendation.clone()),
+        nights,
+    }
+}
+
+/// Length of a workout in seconds, if its timestamps parse.
+pub fn workout_duration(workout: &Workout) -> Option<i64> {
+    let start = DateTime::parse_from_rfc3339(&workout.start_datetime).ok()?;
+    let end = DateTime::parse_from_rfc3339(&workout.end_datetime).ok()?;
+    Some((end - start).num_seconds())
+}
+
+/// Everything logged for one activity type.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkoutTotals {
+    pub activity: String,
+    pub count: usize,
+    /// Seconds.
+    pub duration: i64,
+    /// kcal.
+    pub calories: f64,
+    /// Meters.
+    pub distance: f64,
+}
+
+/// Totals per activity type, longest total duration first.
+pub fn workout_totals(workouts: &[Workout]) -> Vec<WorkoutTotals> {
+    let mut by_activity: BTreeMap<&str, WorkoutTotals> = BTreeMap::new();
+    for workout in workouts {
+        let totals = by_activity
+            .entry(&workout.activity)
+            .or_insert_with(|| WorkoutTotals {
+                activity: workout.activity.clone(),
+                count: 0,
+                duration: 0,
+                calories: 0.0,
+                distance: 0.0,
+            });
+        totals.count += 1;
+        totals.duration += workout_duration(workout).unwrap_or(0);
+        totals.calories += workout.calories.unwrap_or(0.0);
+        totals.distance += workout.distance.unwrap_or(0.0);
+    }
+
+    let mut totals: Vec<_> = by_activity.into_values().collect();
+    totals.sort_by_key(|t| std::cmp::Reverse(t.duration));
+    totals
+}
+
+/// Length of a session in seconds, if its timestamps parse.
+pub fn session_duration(session: &Session) -> Option<i64> {
+    let start = DateTime::parse_from_rfc3339(&session.start_datetime).ok()?;
+    let end = DateTime::parse_from_rfc3339(&session.end_datetime).ok()?;
+    Some((end - start).num_seconds())
+}
+
+/// How long the opening and closing windows of a session are, in seconds.
+const SESSION_WINDOW_SECONDS: i64 = 60;
+
+/// Average of a series over its first and last minute, to show how a
+/// session moved heart rate or HRV.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BeforeAfter {
+    pub before: f64,
+    pub after: f64,
+}
+
+pub fn before_after(series: &TimeSeries) -> Option<BeforeAfter> {
+    let samples: Vec<_> = series.samples().collect();
+    let (first, last) = (samples.first()?.0, samples.last()?.0);
+    let window = Duration::seconds(SESSION_WINDOW_SECONDS);
+    let average = |keep: &dyn Fn(DateTime<FixedOffset>) -> bool| {
+        let present: Vec<f64> = samples
+            .iter()
+            .filter(|(at, _)| keep(*at))
+            .filter_map(|(_, value)| *value)
+            .collect();
+        (!present.is_empty()).then(|| present.iter().sum::<f64>() / present.len() as f64)
+    };
+
+    Some(BeforeAfter {
+        before: average(&|at| at < first + window)?,
+        after: average(&|at| at > last - window)?,
+    })
+}
+
+/// Seconds as "7h 05m", or "45m" under an hour.
+pub fn format_duration(seconds: i64) -> String {
+    let hours = seconds / 3600;
+    let mins = (seconds % 3600) / 60;
+    if hours > 0 {
+        format!("{hours}h {mins:02}m")
+    } else {
+        format!("{mins}m")
+    }
+}
@@ -0,0 +1,750 @@
+//! Async client: every request goes through a shared limit on how many are
+//! in flight, so independent endpoints can be fetched concurrently without
+//! flooding the API.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, RwLock};
+
+use chrono::{Days, Local, NaiveDate, NaiveTime, SecondsFormat, TimeZone};
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::sync::Semaphore;
+
+use crate::cache::{self, Cache};
+use crate::error::{OuraError, Result};
+use crate::models::{
+    ApiResponse, DailyActivity, DailyCardiovascularAge, DailyReadiness, DailyResilience,
+    DailySleep, DailySpo2, DailyStress, EnhancedTag, HeartRateSample, PersonalInfo, RestModePeriod,
+    RingConfiguration, Session, Sleep, SleepTime, Tag, Vo2Max, Workout,
+};
+use crate::oauth::{OAuthApp, OAuthToken};
+use crate::retry::{self, RetryPolicy};
+
+/// Host used when no other base URL is configured.
+pub const DEFAULT_BASE_URL: &str = "https://api.ouraring.com";
+
+/// Days this recent are always re-fetched: late ring syncs can still change them.
+pub const DEFAULT_REFRESH_DAYS: u64 = 3;
+
+/// Requests one client has in flight at most, unless configured otherwise.
+pub const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// Longest span the `heartrate` endpoint accepts in one request.
+const HEARTRATE_MAX_DAYS: u64 = 30;
+
+/// Raw records grouped by the day they belong to.
+pub(crate) type DayRecords = BTreeMap<NaiveDate, Vec<Value>>;
+
+/// Async client for the Oura API v2 `usercollection` endpoints, for use on a
+/// tokio runtime. [`OuraClient`](crate::OuraClient) wraps it for blocking code.
+///
+/// Range methods take inclusive `start`/`end` days and follow pagination
+/// until the full range is returned. Calls can be joined freely (e.g. with
+/// `tokio::try_join!`); at most `max_concurrency` requests run at once.
+pub struct AsyncOuraClient {
+    client: Client,
+    token: RwLock<String>,
+    oauth: Option<OAuthSession>,
+    base_url: String,
+    max_pages: Option<usize>,
+    on_truncated: Option<TruncatedCallback>,
+    retry: RetryPolicy,
+    /// Only locked between awaits, never across one.
+    cache: Option<Mutex<Cache>>,
+    refresh_days: u64,
+    offline: bool,
+    on_uncached: Option<UncachedCallback>,
+    permits: Semaphore,
+}
+
+/// Called with each token the client refreshes, to persist it.
+type RefreshCallback = Box<dyn Fn(&OAuthToken) + Send + Sync>;
+
+/// Called with the endpoint and page count when `max_pages` cut a request
+/// short.
+type TruncatedCallback = Box<dyn Fn(&str, usize) + Send + Sync>;
+
+/// Called with the endpoint and the days an offline read found no cached
+/// copy of.
+type UncachedCallback = Box<dyn Fn(&str, &[NaiveDate]) + Send + Sync>;
+
+/// What the client needs to renew an OAuth access token by itself.
+struct OAuthSession {
+    app: OAuthApp,
+    /// Held while refreshing, so concurrent requests refresh only once.
+    refresh_token: tokio::sync::Mutex<Option<String>>,
+    on_refresh: Option<RefreshCallback>,
+}
+
+/// Oura API v2 has inconsistent end_date behavior: some endpoints treat it as
+/// inclusive, others as exclusive. Bumping end_date by +1 day ensures we always
+/// get the target date's data regardless.
+fn next_day(date: NaiveDate) -> Result<NaiveDate> {
+    date.succ_opt()
+        .ok_or_else(|| OuraError::InvalidDate(date.to_string()))
+}
+
+fn format_day(date: NaiveDate) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+/// Endpoints whose records are keyed by their start day but change until
+/// they get an `end_day`.
+const SPAN_ENDPOINTS: [&str; 2] = ["rest_mode_period", "enhanced_tag"];
+
+/// Whether `records` include a span that was still open when cached, so the
+/// day can't be trusted however old it is.
+fn has_open_span(endpoint: &str, records: &[Value]) -> bool {
+    SPAN_ENDPOINTS.contains(&endpoint)
+        && records
+            .iter()
+            .any(|record| record.get("end_day").is_none_or(Value::is_null))
+}
+
+/// Query parameters selecting `start..=end`. Sample endpoints take
+/// datetimes instead of days: local midnights, with their UTC offset.
+fn range_params(
+    endpoint: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<[(&'static str, String); 2]> {
+    let end = next_day(end)?;
+    Ok(if endpoint == "heartrate" {
+        [
+            ("start_datetime", local_midnight(start)),
+            ("end_datetime", local_midnight(end)),
+        ]
+    } else {
+        [
+            ("start_date", format_day(start)),
+            ("end_date", format_day(end)),
+        ]
+    })
+}
+
+/// The start of `date` in the local time zone, e.g. `2024-02-13T00:00:00+01:00`.
+/// Where a DST change skips midnight, the UTC midnight is used instead.
+fn local_midnight(date: NaiveDate) -> String {
+    let midnight = date.and_time(NaiveTime::MIN);
+    match Local.from_local_datetime(&midnight).earliest() {
+        Some(time) => time.to_rfc3339_opts(SecondsFormat::Secs, false),
+        None => midnight
+            .and_utc()
+            .to_rfc3339_opts(SecondsFormat::Secs, false),
+    }
+}
+
+impl AsyncOuraClient {
+    /// Creates a client authenticating with a personal access token.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            client: Client::new(),
+            token: RwLock::new(token.into()),
+            oauth: None,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            max_pages: None,
+            on_truncated: None,
+            retry: RetryPolicy::default(),
+            cache: None,
+            refresh_days: DEFAULT_REFRESH_DAYS,
+            offline: false,
+            on_uncached: None,
+            permits: Semaphore::new(DEFAULT_MAX_CONCURRENCY),
+        }
+    }
+
+    /// Creates a client from the `OURA_TOKEN` environment variable.
+    pub fn from_env() -> Result<Self> {
+        let token = std::env::var("OURA_TOKEN").map_err(|_| OuraError::MissingToken)?;
+        Ok(Self::new(token))
+    }
+
+    /// Authenticate with an OAuth2 access token, refreshing it through `app`
+    /// when the API answers 401.
+    pub fn with_oauth(mut self, app: OAuthApp, token: OAuthToken) -> Self {
+        self.token = RwLock::new(token.access_token);
+        self.oauth = Some(OAuthSession {
+            app,
+            refresh_token: tokio::sync::Mutex::new(token.refresh_token),
+            on_refresh: None,
+        });
+        self
+    }
+
+    /// Called with every refreshed token, e.g. to persist it. Only used
+    /// together with `with_oauth`.
+    pub fn on_token_refresh(
+        mut self,
+        callback: impl Fn(&OAuthToken) + Send + Sync + 'static,
+    ) -> Self {
+        if let Some(session) = &mut self.oauth {
+            session.on_refresh = Some(Box::new(callback));
+        }
+        self
+    }
+
+    fn access_token(&self) -> String {
+        self.token.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Swaps in a new access token after `rejected` got a 401. Returns false
+    /// when there is no way to refresh.
+    async fn refresh_access_token(&self, rejected: &str) -> Result<bool> {
+        let Some(session) = &self.oauth else {
+            return Ok(false);
+        };
+        let mut refresh_token = session.refresh_token.lock().await;
+        // Another request already refreshed while this one waited for the lock.
+        if self.access_token() != rejected {
+            return Ok(true);
+        }
+        let Some(current) = refresh_token.clone() else {
+            return Ok(false);
+        };
+
+        // `OAuthApp` talks to the token endpoint with a blocking client.
+        let app = session.app.clone();
+        let token = tokio::task::spawn_blocking(move || app.refresh(&current))
+            .await
+            .map_err(|err| OuraError::OAuth(format!("token refresh did not finish: {err}")))??;
+        *self.token.write().unwrap_or_else(|e| e.into_inner()) = token.access_token.clone();
+        refresh_token.clone_from(&token.refresh_token);
+        if let Some(callback) = &session.on_refresh {
+            callback(&token);
+        }
+        Ok(true)
+    }
+
+    /// Send requests to another host (e.g. a local mock or proxy) instead of the Oura API.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Stop following `next_token` after this many pages per request.
+    pub fn with_max_pages(mut self, max_pages: Option<usize>) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    /// Called with the endpoint and the number of pages fetched whenever
+    /// `max_pages` stops a request while the API still has more data.
+    pub fn on_truncated(mut self, callback: impl Fn(&str, usize) + Send + Sync + 'static) -> Self {
+        self.on_truncated = Some(Box::new(callback));
+        self
+    }
+
+    /// Replaces the default retry policy (3 retries, 500ms doubling backoff).
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Serve days older than the refresh window from `cache`, and store
+    /// whatever is fetched in it.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(Mutex::new(cache));
+        self
+    }
+
+    /// How many of the most recent days (including today) bypass the cache.
+    pub fn with_refresh_days(mut self, days: u64) -> Self {
+        self.refresh_days = days;
+        self
+    }
+
+    /// Never make network requests; answer everything from the cache.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Called with the endpoint and the days missing from the cache whenever
+    /// an offline read has to leave them out.
+    pub fn on_uncached(
+        mut self,
+        callback: impl Fn(&str, &[NaiveDate]) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_uncached = Some(Box::new(callback));
+        self
+    }
+
+    /// Requests allowed in flight at once (at least 1). Waiting out a retry
+    /// doesn't hold a slot.
+    pub fn with_max_concurrency(mut self, max: usize) -> Self {
+        self.permits = Semaphore::new(max.max(1));
+        self
+    }
+
+    /// Sends a GET and returns the body, retrying 429/5xx responses and
+    /// network failures per the client's `RetryPolicy`.
+    async fn get(&self, endpoint: &str, query: &[(&str, &str)]) -> Result<String> {
+        let url = format!("{}/v2/usercollection/{endpoint}", self.base_url);
+        let mut retries = 0;
+        let mut refreshed = false;
+
+        loop {
+            let token = self.access_token();
+            let permit = self
+                .permits
+                .acquire()
+                .await
+                .expect("the semaphore is never closed");
+            let result = self
+                .client
+                .get(&url)
+                .bearer_auth(&token)
+                .query(query)
+                .send()
+                .await;
+            let can_retry = retries < self.retry.max_retries;
+
+            let resp = match result {
+                Ok(resp) => resp,
+                Err(err) if can_retry && (err.is_connect() || err.is_timeout()) => {
+                    drop(permit);
+                    retries += 1;
+                    tokio::time::sleep(self.retry.delay(retries, None)).await;
+                    continue;
+                }
+                Err(err) => return Err(OuraError::Network(err)),
+            };
+
+            let status = resp.status();
+            if status.is_success() {
+                return Ok(resp.text().await?);
+            }
+            drop(permit);
+            if status == StatusCode::UNAUTHORIZED
+                && !refreshed
+                && self.refresh_access_token(&token).await?
+            {
+                refreshed = true;
+                continue;
+            }
+            let retry_after = retry::retry_after(resp.headers());
+            if can_retry && RetryPolicy::is_retryable(status) {
+                retries += 1;
+                tokio::time::sleep(self.retry.delay(retries, retry_after)).await;
+                continue;
+            }
+
+            let body = resp.text().await.unwrap_or_default();
+            return Err(OuraError::from_status(status, retry_after, body));
+        }
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, endpoint: &str, date: NaiveDate) -> Result<Vec<T>> {
+        self.fetch_range(endpoint, date, date).await
+    }
+
+    async fn fetch_range<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<T>> {
+        self.records(endpoint, start, end)
+            .await?
+            .into_iter()
+            .map(|record| Ok(serde_json::from_value(record)?))
+            .collect()
+    }
+
+    /// Records for `start..=end`, taking settled days from the cache and
+    /// fetching the span covering everything else in one request. Days with a
+    /// span that was still open are never settled. Offline, days missing
+    /// from the cache are reported through `on_uncached`.
+    async fn records(
+        &self,
+        endpoint: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<Value>> {
+        if self.cache.is_none() {
+            if self.offline {
+                return Err(OuraError::Offline);
+            }
+            // The range is requested one day long; see `next_day`.
+            let records = self.fetch_pages(endpoint, start, end).await?;
+            return Ok(records
+                .into_iter()
+                .filter(|record| {
+                    cache::record_day(record).is_none_or(|day| (start..=end).contains(&day))
+                })
+                .collect());
+        }
+
+        let settled_before = self.settled_before();
+        let mut by_day = BTreeMap::new();
+        let mut missing = Vec::new();
+        {
+            let cache = self.cache()?;
+            for day in start.iter_days().take_while(|d| *d <= end) {
+                let cached = if self.offline || day < settled_before {
+                    cache
+                        .get(endpoint, day)?
+                        .filter(|records| self.offline || !has_open_span(endpoint, records))
+                } else {
+                    None
+                };
+                match cached {
+                    Some(records) => {
+                        by_day.insert(day, records);
+                    }
+                    None => missing.push(day),
+                }
+            }
+        }
+
+        if let (true, false, Some(callback)) = (self.offline, missing.is_empty(), &self.on_uncached)
+        {
+            callback(endpoint, &missing);
+        }
+
+        let mut undated = Vec::new();
+        if let (Some(&first), Some(&last), false) = (missing.first(), missing.last(), self.offline)
+        {
+            let (fetched, rest) = self.fetch_into_cache(endpoint, first, last).await?;
+            by_day.extend(fetched);
+            undated = rest;
+        }
+
+        Ok(by_day.into_values().flatten().chain(undated).collect())
+    }
+
+    /// First day old enough to trust the cached copy of.
+    pub(crate) fn settled_before(&self) -> NaiveDate {
+        let today = Local::now().date_naive();
+        today
+            .checked_sub_days(Days::new(self.refresh_days.saturating_sub(1)))
+            .unwrap_or(today)
+    }
+
+    /// The cache, locked. Drop the guard before the next `.await`.
+    pub(crate) fn cache(&self) -> Result<MutexGuard<'_, Cache>> {
+        let cache = self.cache.as_ref().ok_or(OuraError::NoCache)?;
+        Ok(cache.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Fetches `start..=end` and stores every day in it, including empty ones,
+    /// so they aren't requested again. Returns the records grouped by day plus
+    /// any that carry no day at all.
+    pub(crate) async fn fetch_into_cache(
+        &self,
+        endpoint: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<(DayRecords, Vec<Value>)> {
+        let mut fetched = DayRecords::new();
+        let mut undated = Vec::new();
+        for record in self.fetch_pages(endpoint, start, end).await? {
+            match cache::record_day(&record) {
+                Some(day) => fetched.entry(day).or_default().push(record),
+                None => undated.push(record),
+            }
+        }
+
+        let cache = self.cache()?;
+        let mut by_day = BTreeMap::new();
+        for day in start.iter_days().take_while(|d| *d <= end) {
+            let records = fetched.remove(&day).unwrap_or_default();
+            cache.put(endpoint, day, &records)?;
+            by_day.insert(day, records);
+        }
+        Ok((by_day, undated))
+    }
+
+    /// Fetches every page of `endpoint` between `start` and `end` (inclusive).
+    async fn fetch_pages(
+        &self,
+        endpoint: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<Value>> {
+        let [(start_key, start), (end_key, end)] = range_params(endpoint, start, end)?;
+        self.paginate(endpoint, &[(start_key, &start), (end_key, &end)])
+            .await
+    }
+
+    /// Follows `next_token` until the API stops returning one or the page cap
+    /// is reached, returning the records of every page. Hitting the cap is
+    /// reported through `on_truncated`.
+    async fn paginate(&self, endpoint: &str, params: &[(&str, &str)]) -> Result<Vec<Value>> {
+        let mut data = Vec::new();
+        let mut next_token: Option<String> = None;
+        let mut pages = 0usize;
+
+        loop {
+            let mut query = params.to_vec();
+            if let Some(token) = next_token.as_deref() {
+                query.push(("next_token", token));
+            }
+
+            let body: ApiResponse<Value> =
+                serde_json::from_str(&self.get(endpoint, &query).await?)?;
+            data.extend(body.data);
+            pages += 1;
+
+            match body.next_token {
+                Some(token) if self.max_pages.is_none_or(|max| pages < max) => {
+                    next_token = Some(token);
+                }
+                Some(_) => {
+                    if let Some(callback) = &self.on_truncated {
+                        callback(endpoint, pages);
+                    }
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// The account the token belongs to. Never cached.
+    pub async fn personal_info(&self) -> Result<PersonalInfo> {
+        if self.offline {
+            return Err(OuraError::Offline);
+        }
+        Ok(serde_json::from_str(
+            &self.get("personal_info", &[]).await?,
+        )?)
+    }
+
+    /// Every ring registered to the account. Never cached.
+    pub async fn ring_configurations(&self) -> Result<Vec<RingConfiguration>> {
+        if self.offline {
+            return Err(OuraError::Offline);
+        }
+        self.paginate("ring_configuration", &[])
+            .await?
+            .into_iter()
+            .map(|record| Ok(serde_json::from_value(record)?))
+            .collect()
+    }
+
+    pub async fn daily_sleep(&self, date: NaiveDate) -> Result<Vec<DailySleep>> {
+        self.fetch("daily_sleep", date).await
+    }
+
+    pub async fn daily_readiness(&self, date: NaiveDate) -> Result<Vec<DailyReadiness>> {
+        self.fetch("daily_readiness", date).await
+    }
+
+    pub async fn daily_activity(&self, date: NaiveDate) -> Result<Vec<DailyActivity>> {
+        self.fetch("daily_activity", date).await
+    }
+
+    /// Detailed sleep periods (main sleep, naps, rest) for the day.
+    pub async fn sleep(&self, date: NaiveDate) -> Result<Vec<Sleep>> {
+        self.fetch("sleep", date).await
+    }
+
+    pub async fn daily_stress(&self, date: NaiveDate) -> Result<Vec<DailyStress>> {
+        self.fetch("daily_stress", date).await
+    }
+
+    /// Heart rate samples for the local day, from every source.
+    pub async fn heartrate(&self, date: NaiveDate) -> Result<Vec<HeartRateSample>> {
+        self.fetch("heartrate", date).await
+    }
+
+    pub async fn daily_spo2(&self, date: NaiveDate) -> Result<Vec<DailySpo2>> {
+        self.fetch("daily_spo2", date).await
+    }
+
+    pub async fn daily_cardiovascular_age(
+        &self,
+        date: NaiveDate,
+    ) -> Result<Vec<DailyCardiovascularAge>> {
+        self.fetch("daily_cardiovascular_age", date).await
+    }
+
+    pub async fn daily_resilience(&self, date: NaiveDate) -> Result<Vec<DailyResilience>> {
+        self.fetch("daily_resilience", date).await
+    }
+
+    pub async fn vo2_max(&self, date: NaiveDate) -> Result<Vec<Vo2Max>> {
+        self.fetch("vO2_max", date).await
+    }
+
+    /// Recommended bedtime for the night starting on the day.
+    pub async fn sleep_time(&self, date: NaiveDate) -> Result<Vec<SleepTime>> {
+        self.fetch("sleep_time", date).await
+    }
+
+    pub async fn workouts(&self, date: NaiveDate) -> Result<Vec<Workout>> {
+        self.fetch("workout", date).await
+    }
+
+    /// Meditation, breathing and rest sessions recorded in the app.
+    pub async fn sessions(&self, date: NaiveDate) -> Result<Vec<Session>> {
+        self.fetch("session", date).await
+    }
+
+    /// Tags in the original format (see [`Self::enhanced_tags`] for current ones).
+    pub async fn tags(&self, date: NaiveDate) -> Result<Vec<Tag>> {
+        self.fetch("tag", date).await
+    }
+
+    /// Tagged events starting on the day.
+    pub async fn enhanced_tags(&self, date: NaiveDate) -> Result<Vec<EnhancedTag>> {
+        self.fetch("enhanced_tag", date).await
+    }
+
+    pub async fn daily_sleep_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DailySleep>> {
+        self.fetch_range("daily_sleep", start, end).await
+    }
+
+    pub async fn daily_readiness_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DailyReadiness>> {
+        self.fetch_range("daily_readiness", start, end).await
+    }
+
+    pub async fn daily_activity_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DailyActivity>> {
+        self.fetch_range("daily_activity", start, end).await
+    }
+
+    pub async fn sleep_range(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<Sleep>> {
+        self.fetch_range("sleep", start, end).await
+    }
+
+    pub async fn daily_stress_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DailyStress>> {
+        self.fetch_range("daily_stress", start, end).await
+    }
+
+    pub async fn daily_spo2_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DailySpo2>> {
+        self.fetch_range("daily_spo2", start, end).await
+    }
+
+    pub async fn daily_cardiovascular_age_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DailyCardiovascularAge>> {
+        self.fetch_range("daily_cardiovascular_age", start, end)
+            .await
+    }
+
+    pub async fn daily_resilience_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<DailyResilience>> {
+        self.fetch_range("daily_resilience", start, end).await
+    }
+
+    pub async fn vo2_max_range(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<Vo2Max>> {
+        self.fetch_range("vO2_max", start, end).await
+    }
+
+    /// Rest Mode periods starting in `start..=end`.
+    pub async fn rest_mode_periods_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<RestModePeriod>> {
+        self.fetch_range("rest_mode_period", start, end).await
+    }
+
+    pub async fn sleep_time_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<SleepTime>> {
+        self.fetch_range("sleep_time", start, end).await
+    }
+
+    pub async fn workouts_range(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<Workout>> {
+        self.fetch_range("workout", start, end).await
+    }
+
+    pub async fn sessions_range(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<Session>> {
+        self.fetch_range("session", start, end).await
+    }
+
+    pub async fn tags_range(&self, start: NaiveDate, end: NaiveDate) -> Result<Vec<Tag>> {
+        self.fetch_range("tag", start, end).await
+    }
+
+    pub async fn enhanced_tags_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<EnhancedTag>> {
+        self.fetch_range("enhanced_tag", start, end).await
+    }
+
+    /// Heart rate samples for the local days `start..=end`, split into
+    /// requests the API accepts.
+    pub async fn heartrate_range(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Vec<HeartRateSample>> {
+        let mut samples = Vec::new();
+        let mut chunk_start = start;
+        while chunk_start <= end {
+            let chunk_end = chunk_start
+                .checked_add_days(Days::new(HEARTRATE_MAX_DAYS - 1))
+                .map_or(end, |d| d.min(end));
+            samples.extend(
+                self.fetch_range::<HeartRateSample>("heartrate", chunk_start, chunk_end)
+                    .await?,
+            );
+            chunk_start = next_day(chunk_end)?;
+        }
+        Ok(samples)
+    }
+
+    /// Every record of any `usercollection` endpoint in `start..=end`, in the
+    /// API's `{"data": [...]}` shape with all pages merged.
+    pub async fn raw_range(
+        &self,
+        endpoint: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<serde_json::Value> {
+        let data = if self.offline {
+            self.records(endpoint, start, end).await?
+        } else {
+            self.fetch_pages(endpoint, start, end).await?
+        };
+        Ok(serde_json::json!({ "data": data, "next_token": null }))
+    }
+
+    /// The unparsed first page of any `usercollection` endpoint for one day.
+    /// Offline, the cached records are wrapped in the same `{"data": [...]}` shape.
+    pub async fn raw(&self, endpoint: &str, date: NaiveDate) -> Result<serde_json::Value> {
+        if self.offline {
+            return self.raw_range(endpoint, date, date).await;
+        }
+        let [(start_key, start), (end_key, end)] = range_params(endpoint, date, date)?;
+        let text = self
+            .get(endpoint, &[(start_key, &start), (end_key, &end)])
+            .await?;
+        Ok(serde_json::from_str(&text)?)
+    }
+}
@@ -0,0 +1,356 @@
+use std::fmt;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use age::secrecy::{ExposeSecret, SecretString};
+use anyhow::{Context, Result, bail};
+use oura_cli::oauth::{
+    DEFAULT_AUTHORIZE_URL, DEFAULT_LOGIN_TIMEOUT, Loopback, OAuthApp, OAuthToken, Pkce,
+    random_string,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::{Config, TokenSettings};
+
+/// Service name tokens are stored under in the OS keyring.
+const KEYRING_SERVICE: &str = "oura-cli";
+
+/// Where the token in use came from, in precedence order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenSource {
+    Flag,
+    Env,
+    Keyring,
+    EncryptedFile(PathBuf),
+    Config,
+}
+
+impl fmt::Display for TokenSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Flag => f.write_str("--token flag"),
+            Self::Env => f.write_str("OURA_TOKEN environment variable"),
+            Self::Keyring => f.write_str("OS keyring"),
+            Self::EncryptedFile(path) => write!(f, "encrypted file {}", path.display()),
+            Self::Config => f.write_str("config file"),
+        }
+    }
+}
+
+/// A stored OAuth login: the tokens plus what is needed to refresh them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthLogin {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub token_url: String,
+    #[serde(flatten)]
+    pub token: OAuthToken,
+}
+
+impl OAuthLogin {
+    pub fn new(app: &OAuthApp, token: OAuthToken) -> Self {
+        Self {
+            client_id: app.client_id.clone(),
+            client_secret: app.client_secret.clone(),
+            token_url: app.token_url.clone(),
+            token,
+        }
+    }
+
+    pub fn app(&self) -> OAuthApp {
+        OAuthApp::new(&self.client_id, self.client_secret.clone())
+            .with_endpoints(DEFAULT_AUTHORIZE_URL, &self.token_url)
+    }
+}
+
+/// What authenticates requests.
+#[derive(Debug, Clone)]
+pub enum Credential {
+    /// A personal access token.
+    Token(String),
+    OAuth(OAuthLogin),
+}
+
+impl Credential {
+    /// Stands for whose data this credential reads, without revealing the
+    /// secret. OAuth access tokens rotate, so a login is identified by its
+    /// app and where it's stored instead.
+    pub fn fingerprint(&self, source: &TokenSource) -> String {
+        let id = match self {
+            Self::Token(token) => token.clone(),
+            Self::OAuth(login) => format!("oauth\0{}\0{source}", login.client_id),
+        };
+        Sha256::digest(id.as_bytes())
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    /// Stored secrets are either a bare token or an `OAuthLogin` as JSON.
+    fn parse(stored: String) -> Self {
+        match serde_json::from_str(&stored) {
+            Ok(login) => Self::OAuth(login),
+            Err(_) => Self::Token(stored),
+        }
+    }
+}
+
+/// Stored credentials for one profile or named account: an OS keyring entry
+/// and/or a passphrase-encrypted file, plus the config's token source.
+pub struct Store {
+    account: String,
+    file: Option<PathBuf>,
+    settings: TokenSettings,
+    /// Named accounts ignore `--token` and `OURA_TOKEN`, which belong to
+    /// whoever runs the CLI.
+    named: bool,
+    /// Remembered after the first use, so a refreshed token can be written
+    /// back without asking again.
+    passphrase: Mutex<Option<SecretString>>,
+}
+
+impl Store {
+    /// The store for `account` (`[accounts.NAME]`), or for the profile's
+    /// default account when `None`.
+    pub fn for_config(config: &Config, account: Option<&str>) -> Result<Self> {
+        let (user, file_name, settings) = match (account, &config.profile) {
+            (Some(name), _) => (
+                format!("account:{name}"),
+                format!("token-account-{name}.age"),
+                config.account(name)?.clone(),
+            ),
+            (None, Some(profile)) => (
+                profile.clone(),
+                format!("token-{profile}.age"),
+                config.credentials.clone(),
+            ),
+            (None, None) => (
+                "default".into(),
+                "token.age".into(),
+                config.credentials.clone(),
+            ),
+        };
+        Ok(Self {
+            account: user,
+            file: Config::path().and_then(|p| Some(p.parent()?.join(file_name))),
+            settings,
+            named: account.is_some(),
+            passphrase: Mutex::new(None),
+        })
+    }
+
+    /// The keyring entry, unless `OURA_NO_KEYRING` is set (e.g. on headless
+    /// machines without a Secret Service).
+    fn entry(&self) -> Option<keyring::Entry> {
+        if std::env::var_os("OURA_NO_KEYRING").is_some_and(|v| !v.is_empty()) {
+            return None;
+        }
+        keyring::Entry::new(KEYRING_SERVICE, &self.account).ok()
+    }
+
+    /// Finds a token by precedence: `--token`, `OURA_TOKEN`, the keyring, the
+    /// encrypted file, then the config's token source. Named accounts start
+    /// at the keyring.
+    pub fn resolve(&self, flag: Option<&str>) -> Result<Option<(Credential, TokenSource)>> {
+        if !self.named {
+            if let Some(token) = flag.filter(|t| !t.is_empty()) {
+                return Ok(Some((
+                    Credential::Token(token.to_string()),
+                    TokenSource::Flag,
+                )));
+            }
+            if let Some(token) = std::env::var("OURA_TOKEN").ok().filter(|t| !t.is_empty()) {
+                return Ok(Some((Credential::Token(token), TokenSource::Env)));
+            }
+        }
+        // An unreachable keyring (no Secret Service, locked, ...) just means
+        // nothing is stored there.
+        if let Some(stored) = self.entry().and_then(|e| e.get_password().ok()) {
+            return Ok(Some((Credential::parse(stored), TokenSource::Keyring)));
+        }
+        if let Some(path) = self.file.as_deref().filter(|p| p.exists()) {
+            let stored = self.decrypt_file(path)?;
+            return Ok(Some((
+                Credential::parse(stored),
+                TokenSource::EncryptedFile(path.to_path_buf()),
+            )));
+        }
+        Ok(self
+            .settings
+            .token()?
+            .map(|token| (Credential::Token(token), TokenSource::Config)))
+    }
+
+    /// Saves `secret` to the keyring, or to the encrypted file when `file` is
+    /// set. Returns where it went.
+    pub fn save(&self, secret: &str, file: bool) -> Result<TokenSource> {
+        if !file {
+            let Some(entry) = self.entry() else {
+                bail!("the keyring is disabled (OURA_NO_KEYRING); use --file");
+            };
+            entry
+                .set_password(secret)
+                .context("Failed to store the token in the keyring; try --file")?;
+            return Ok(TokenSource::Keyring);
+        }
+
+        let path = self.file.clone().context("No config directory")?;
+        let recipient = age::scrypt::Recipient::new(self.passphrase(true)?);
+        let encrypted =
+            age::encrypt(&recipient, secret.as_bytes()).context("Failed to encrypt the token")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        write_private(&path, &encrypted)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(TokenSource::EncryptedFile(path))
+    }
+
+    /// Writes a refreshed OAuth login back to where it was read from.
+    pub fn update(&self, source: &TokenSource, login: &OAuthLogin) -> Result<()> {
+        let secret = serde_json::to_string(login)?;
+        match source {
+            TokenSource::Keyring => self.save(&secret, false).map(drop),
+            TokenSource::EncryptedFile(_) => self.save(&secret, true).map(drop),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes the keyring entry and encrypted file; returns what was removed.
+    pub fn clear(&self) -> Result<Vec<TokenSource>> {
+        let mut removed = Vec::new();
+        if let Some(entry) = self.entry() {
+            match entry.delete_credential() {
+                Ok(()) => removed.push(TokenSource::Keyring),
+                Err(keyring::Error::NoEntry) => {}
+                // Nothing can be stored in a keyring we can't reach.
+                Err(keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_)) => {}
+                Err(err) => return Err(err).context("Failed to remove the token from the keyring"),
+            }
+        }
+        if let Some(path) = self.file.as_deref().filter(|p| p.exists()) {
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            removed.push(TokenSource::EncryptedFile(path.to_path_buf()));
+        }
+        Ok(removed)
+    }
+}
+
+impl Store {
+    fn decrypt_file(&self, path: &Path) -> Result<String> {
+        let encrypted =
+            std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let identity = age::scrypt::Identity::new(self.passphrase(false)?);
+        let secret = age::decrypt(&identity, &encrypted)
+            .with_context(|| format!("Failed to decrypt {} (wrong passphrase?)", path.display()))?;
+        String::from_utf8(secret).context("Decrypted token is not valid UTF-8")
+    }
+
+    /// `OURA_PASSPHRASE`, or a prompt on the terminal (asked twice when
+    /// `confirm`). Only asks once per run.
+    fn passphrase(&self, confirm: bool) -> Result<SecretString> {
+        let mut remembered = self.passphrase.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(pass) = remembered.as_ref() {
+            return Ok(pass.expose_secret().into());
+        }
+        let pass = match std::env::var("OURA_PASSPHRASE")
+            .ok()
+            .filter(|p| !p.is_empty())
+        {
+            Some(pass) => pass,
+            None => {
+                if !std::io::stdin().is_terminal() {
+                    bail!("the token file is encrypted; set OURA_PASSPHRASE");
+                }
+                let pass = rpassword::prompt_password("Passphrase for the token file: ")?;
+                if confirm && rpassword::prompt_password("Repeat passphrase: ")? != pass {
+                    bail!("passphrases don't match");
+                }
+                pass
+            }
+        };
+        *remembered = Some(pass.as_str().into());
+        Ok(pass.into())
+    }
+}
+
+/// Runs the browser half of the OAuth flow: prints (and tries to open) the
+/// authorization page, waits for the redirect on a loopback port and
+/// exchanges the code.
+pub fn oauth_login(
+    app: &OAuthApp,
+    scopes: &[&str],
+    redirect_uri: &str,
+    open_browser: bool,
+) -> Result<OAuthToken> {
+    let loopback = Loopback::bind(redirect_uri)
+        .with_context(|| format!("Failed to listen for the redirect to {redirect_uri}"))?;
+    let redirect_uri = loopback.redirect_uri();
+    let pkce = Pkce::new()?;
+    let state = random_string()?;
+    let url = app.authorize_url(redirect_uri, scopes, &state, &pkce)?;
+
+    eprintln!("Open this page to authorize oura-cli:\n\n  {url}\n");
+    if open_browser {
+        open_in_browser(&url);
+    }
+    eprintln!("Waiting for the redirect to {redirect_uri} ...");
+    let code = loopback.wait_for_code(&state, DEFAULT_LOGIN_TIMEOUT)?;
+    Ok(app.exchange_code(&code, redirect_uri, &pkce)?)
+}
+
+/// Best effort; the URL is printed either way.
+fn open_in_browser(url: &str) {
+    let mut cmd = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut cmd = std::process::Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    let _ = cmd
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+}
+
+/// Reads a token from a hidden prompt, or from stdin when piped.
+pub fn read_token() -> Result<String> {
+    let token = if std::io::stdin().is_terminal() {
+        rpassword::prompt_password("Paste your personal access token: ")?
+    } else {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        line
+    };
+    let token = token.trim().to_string();
+    if token.is_empty() {
+        bail!("no token given");
+    }
+    Ok(token)
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(contents)
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
@@ -0,0 +1,103 @@
+//! On-disk store of fetched records, keyed by endpoint and day.
+
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use rusqlite::{Connection, OptionalExtension, params};
+use serde_json::Value;
+
+use crate::error::Result;
+use crate::models::local_day;
+
+/// SQLite-backed cache of API records. Each row holds every record an
+/// endpoint returned for one day, so an empty day is cached too.
+pub struct Cache {
+    conn: Connection,
+}
+
+impl Cache {
+    /// `$XDG_DATA_HOME/oura/cache.sqlite` (or the platform equivalent). It's
+    /// kept with data rather than caches, which cleaners may wipe, because
+    /// `oura sync` archives history into it.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("oura").join("cache.sqlite"))
+    }
+
+    /// Opens (creating if needed) the cache database at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS records (
+                endpoint   TEXT NOT NULL,
+                day        TEXT NOT NULL,
+                data       TEXT NOT NULL,
+                fetched_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (endpoint, day)
+            );
+            CREATE TABLE IF NOT EXISTS sync_state (
+                endpoint    TEXT PRIMARY KEY,
+                synced_from TEXT NOT NULL,
+                synced_to   TEXT NOT NULL
+            );",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Records cached for `endpoint` on `day`, or `None` if the day was never fetched.
+    pub fn get(&self, endpoint: &str, day: NaiveDate) -> Result<Option<Vec<Value>>> {
+        let data: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT data FROM records WHERE endpoint = ?1 AND day = ?2",
+                params![endpoint, day.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(data.map(|d| serde_json::from_str(&d)).transpose()?)
+    }
+
+    /// Replaces whatever was cached for `endpoint` on `day`.
+    pub fn put(&self, endpoint: &str, day: NaiveDate, records: &[Value]) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO records (endpoint, day, data, fetched_at)
+             VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)",
+            params![endpoint, day.to_string(), serde_json::to_string(records)?],
+        )?;
+        Ok(())
+    }
+
+    /// The span of days `oura sync` has fully archived for `endpoint`.
+    pub fn synced_range(&self, endpoint: &str) -> Result<Option<(NaiveDate, NaiveDate)>> {
+        let row: Option<(String, String)> = self
+            .conn
+            .query_row(
+                "SELECT synced_from, synced_to FROM sync_state WHERE endpoint = ?1",
+                params![endpoint],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(row.and_then(|(from, to)| Some((from.parse().ok()?, to.parse().ok()?))))
+    }
+
+    pub fn set_synced_range(&self, endpoint: &str, from: NaiveDate, to: NaiveDate) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO sync_state (endpoint, synced_from, synced_to)
+             VALUES (?1, ?2, ?3)",
+            params![endpoint, from.to_string(), to.to_string()],
+        )?;
+        Ok(())
+    }
+}
+
+/// The day a record belongs to: `day` for daily summaries, `start_day` for
+/// spans like tags, or the local day of `timestamp` for samples.
+pub fn record_day(record: &Value) -> Option<NaiveDate> {
+    ["day", "start_day"]
+        .iter()
+        .filter_map(|key| record.get(key)?.as_str())
+        .find_map(|s| s.get(..10)?.parse().ok())
+        .or_else(|| local_day(record.get("timestamp")?.as_str()?))
+}
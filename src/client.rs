@@ -1,133 +1,195 @@
-use anyhow::{Context, Result, bail};
-use chrono::NaiveDate;
-use reqwest::blocking::Client;
-use serde::de::DeserializeOwned;
+//! Blocking facade over [`AsyncOuraClient`], for callers without an async runtime.
 
-use crate::models::{ApiResponse, DailyActivity, DailyReadiness, DailySleep, DailyStress, Sleep};
+use std::future::Future;
 
+use chrono::NaiveDate;
+use serde_json::Value;
+use tokio::runtime::Runtime;
+
+use crate::async_client::AsyncOuraClient;
+use crate::cache::Cache;
+use crate::error::{OuraError, Result};
+use crate::models::{
+    DailyActivity, DailyCardiovascularAge, DailyReadiness, DailyResilience, DailySleep, DailySpo2,
+    DailyStress, EnhancedTag, HeartRateSample, PersonalInfo, RestModePeriod, RingConfiguration,
+    Session, Sleep, SleepTime, Tag, Vo2Max, Workout,
+};
+use crate::oauth::{OAuthApp, OAuthToken};
+use crate::retry::RetryPolicy;
+use crate::sync::{Endpoint, SyncedChunk};
+
+pub use crate::async_client::{DEFAULT_BASE_URL, DEFAULT_MAX_CONCURRENCY, DEFAULT_REFRESH_DAYS};
+
+/// Blocking client for the Oura API v2 `usercollection` endpoints.
+///
+/// Range methods take inclusive `start`/`end` days and follow pagination
+/// until the full range is returned. Each method runs the
+/// [`AsyncOuraClient`] method of the same name to completion on the client's
+/// own single-threaded runtime, so it must not be called from async code. To
+/// fetch several endpoints at once, join calls on [`Self::as_async`] inside
+/// [`Self::block_on`].
 pub struct OuraClient {
-    client: Client,
-    token: String,
+    inner: AsyncOuraClient,
+    runtime: Runtime,
 }
 
-/// Oura API v2 has inconsistent end_date behavior: some endpoints treat it as
-/// inclusive, others as exclusive. Bumping end_date by +1 day ensures we always
-/// get the target date's data regardless.
-fn next_day(date: &str) -> Result<String> {
-    let d = NaiveDate::parse_from_str(date, "%Y-%m-%d").context("Invalid date format")?;
-    Ok(d.succ_opt()
-        .expect("date overflow")
-        .format("%Y-%m-%d")
-        .to_string())
+/// Generates methods that block on the async client's method of the same name.
+macro_rules! blocking {
+    ($(fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        $(
+            pub fn $name(&self $(, $arg: $ty)*) -> Result<$ret> {
+                self.runtime.block_on(self.inner.$name($($arg),*))
+            }
+        )*
+    };
 }
 
 impl OuraClient {
-    pub fn new() -> Result<Self> {
-        let token = std::env::var("OURA_TOKEN").context(
-            "OURA_TOKEN not set. Get your token at https://cloud.ouraring.com/personal-access-tokens",
-        )?;
-        Ok(Self {
-            client: Client::new(),
-            token,
-        })
-    }
-
-    fn fetch<T: DeserializeOwned>(&self, endpoint: &str, date: &str) -> Result<Vec<T>> {
-        let end = next_day(date)?;
-        let url = format!("https://api.ouraring.com/v2/usercollection/{endpoint}");
-        let resp = self
-            .client
-            .get(&url)
-            .bearer_auth(&self.token)
-            .query(&[("start_date", date), ("end_date", &end)])
-            .send()
-            .context("Failed to reach Oura API")?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let body = resp.text().unwrap_or_default();
-            bail!("Oura API returned {status}: {body}");
-        }
+    /// Creates a client authenticating with a personal access token. Fails
+    /// only if the client's runtime can't be started.
+    pub fn new(token: impl Into<String>) -> Result<Self> {
+        Self::from_async(AsyncOuraClient::new(token))
+    }
 
-        let body: ApiResponse<T> = resp.json().context("Failed to parse API response")?;
-        Ok(body.data)
+    /// Creates a client from the `OURA_TOKEN` environment variable.
+    pub fn from_env() -> Result<Self> {
+        Self::from_async(AsyncOuraClient::from_env()?)
     }
 
-    fn fetch_range<T: DeserializeOwned>(
-        &self,
-        endpoint: &str,
-        start: &str,
-        end: &str,
-    ) -> Result<Vec<T>> {
-        let end_plus = next_day(end)?;
-        let url = format!("https://api.ouraring.com/v2/usercollection/{endpoint}");
-        let resp = self
-            .client
-            .get(&url)
-            .bearer_auth(&self.token)
-            .query(&[("start_date", start), ("end_date", &end_plus)])
-            .send()
-            .context("Failed to reach Oura API")?;
-
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let body = resp.text().unwrap_or_default();
-            bail!("Oura API returned {status}: {body}");
+    /// Wraps a configured async client.
+    pub fn from_async(inner: AsyncOuraClient) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(OuraError::Runtime)?;
+        Ok(Self { inner, runtime })
+    }
+
+    /// The async client underneath, for joining requests in [`Self::block_on`].
+    pub fn as_async(&self) -> &AsyncOuraClient {
+        &self.inner
+    }
+
+    /// Runs `future` to completion on the client's runtime.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    fn map(self, f: impl FnOnce(AsyncOuraClient) -> AsyncOuraClient) -> Self {
+        Self {
+            inner: f(self.inner),
+            ..self
         }
+    }
 
-        let body: ApiResponse<T> = resp.json().context("Failed to parse API response")?;
-        Ok(body.data)
+    /// Authenticate with an OAuth2 access token, refreshing it through `app`
+    /// when the API answers 401.
+    pub fn with_oauth(self, app: OAuthApp, token: OAuthToken) -> Self {
+        self.map(|c| c.with_oauth(app, token))
     }
 
-    pub fn daily_sleep(&self, date: &str) -> Result<Vec<DailySleep>> {
-        self.fetch("daily_sleep", date)
+    /// Called with every refreshed token, e.g. to persist it. Only used
+    /// together with `with_oauth`.
+    pub fn on_token_refresh(self, callback: impl Fn(&OAuthToken) + Send + Sync + 'static) -> Self {
+        self.map(|c| c.on_token_refresh(callback))
     }
 
-    pub fn daily_readiness(&self, date: &str) -> Result<Vec<DailyReadiness>> {
-        self.fetch("daily_readiness", date)
+    /// Send requests to another host (e.g. a local mock or proxy) instead of the Oura API.
+    pub fn with_base_url(self, base_url: impl Into<String>) -> Self {
+        self.map(|c| c.with_base_url(base_url))
     }
 
-    pub fn daily_activity(&self, date: &str) -> Result<Vec<DailyActivity>> {
-        self.fetch("daily_activity", date)
+    /// Stop following `next_token` after this many pages per request.
+    pub fn with_max_pages(self, max_pages: Option<usize>) -> Self {
+        self.map(|c| c.with_max_pages(max_pages))
     }
 
-    pub fn sleep(&self, date: &str) -> Result<Vec<Sleep>> {
-        self.fetch("sleep", date)
+    /// Called with the endpoint and the number of pages fetched whenever
+    /// `max_pages` stops a request while the API still has more data.
+    pub fn on_truncated(self, callback: impl Fn(&str, usize) + Send + Sync + 'static) -> Self {
+        self.map(|c| c.on_truncated(callback))
     }
 
-    pub fn daily_stress(&self, date: &str) -> Result<Vec<DailyStress>> {
-        self.fetch("daily_stress", date)
+    /// Replaces the default retry policy (3 retries, 500ms doubling backoff).
+    pub fn with_retry(self, retry: RetryPolicy) -> Self {
+        self.map(|c| c.with_retry(retry))
     }
 
-    pub fn daily_sleep_range(&self, start: &str, end: &str) -> Result<Vec<DailySleep>> {
-        self.fetch_range("daily_sleep", start, end)
+    /// Serve days older than the refresh window from `cache`, and store
+    /// whatever is fetched in it.
+    pub fn with_cache(self, cache: Cache) -> Self {
+        self.map(|c| c.with_cache(cache))
     }
 
-    pub fn daily_readiness_range(&self, start: &str, end: &str) -> Result<Vec<DailyReadiness>> {
-        self.fetch_range("daily_readiness", start, end)
+    /// How many of the most recent days (including today) bypass the cache.
+    pub fn with_refresh_days(self, days: u64) -> Self {
+        self.map(|c| c.with_refresh_days(days))
     }
 
-    pub fn daily_activity_range(&self, start: &str, end: &str) -> Result<Vec<DailyActivity>> {
-        self.fetch_range("daily_activity", start, end)
+    /// Never make network requests; answer everything from the cache.
+    pub fn with_offline(self, offline: bool) -> Self {
+        self.map(|c| c.with_offline(offline))
     }
 
-    pub fn raw(&self, endpoint: &str, date: &str) -> Result<serde_json::Value> {
-        let end = next_day(date)?;
-        let url = format!("https://api.ouraring.com/v2/usercollection/{endpoint}");
-        let resp = self
-            .client
-            .get(&url)
-            .bearer_auth(&self.token)
-            .query(&[("start_date", date), ("end_date", &end)])
-            .send()
-            .context("Failed to reach Oura API")?;
+    /// Called with the endpoint and the days missing from the cache whenever
+    /// an offline read has to leave them out.
+    pub fn on_uncached(
+        self,
+        callback: impl Fn(&str, &[NaiveDate]) + Send + Sync + 'static,
+    ) -> Self {
+        self.map(|c| c.on_uncached(callback))
+    }
 
-        if !resp.status().is_success() {
-            let status = resp.status();
-            let body = resp.text().unwrap_or_default();
-            bail!("Oura API returned {status}: {body}");
-        }
+    /// Requests allowed in flight at once when calls are joined (at least 1).
+    pub fn with_max_concurrency(self, max: usize) -> Self {
+        self.map(|c| c.with_max_concurrency(max))
+    }
+
+    /// See [`AsyncOuraClient::sync`].
+    pub fn sync(
+        &self,
+        endpoint: &Endpoint,
+        since: Option<NaiveDate>,
+        on_chunk: impl FnMut(&SyncedChunk),
+    ) -> Result<usize> {
+        self.block_on(self.inner.sync(endpoint, since, on_chunk))
+    }
 
-        resp.json().context("Failed to parse response")
+    blocking! {
+        fn personal_info() -> PersonalInfo;
+        fn ring_configurations() -> Vec<RingConfiguration>;
+        fn daily_sleep(date: NaiveDate) -> Vec<DailySleep>;
+        fn daily_readiness(date: NaiveDate) -> Vec<DailyReadiness>;
+        fn daily_activity(date: NaiveDate) -> Vec<DailyActivity>;
+        fn sleep(date: NaiveDate) -> Vec<Sleep>;
+        fn daily_stress(date: NaiveDate) -> Vec<DailyStress>;
+        fn heartrate(date: NaiveDate) -> Vec<HeartRateSample>;
+        fn daily_spo2(date: NaiveDate) -> Vec<DailySpo2>;
+        fn daily_cardiovascular_age(date: NaiveDate) -> Vec<DailyCardiovascularAge>;
+        fn daily_resilience(date: NaiveDate) -> Vec<DailyResilience>;
+        fn vo2_max(date: NaiveDate) -> Vec<Vo2Max>;
+        fn sleep_time(date: NaiveDate) -> Vec<SleepTime>;
+        fn workouts(date: NaiveDate) -> Vec<Workout>;
+        fn sessions(date: NaiveDate) -> Vec<Session>;
+        fn tags(date: NaiveDate) -> Vec<Tag>;
+        fn enhanced_tags(date: NaiveDate) -> Vec<EnhancedTag>;
+        fn daily_sleep_range(start: NaiveDate, end: NaiveDate) -> Vec<DailySleep>;
+        fn daily_readiness_range(start: NaiveDate, end: NaiveDate) -> Vec<DailyReadiness>;
+        fn daily_activity_range(start: NaiveDate, end: NaiveDate) -> Vec<DailyActivity>;
+        fn sleep_range(start: NaiveDate, end: NaiveDate) -> Vec<Sleep>;
+        fn daily_stress_range(start: NaiveDate, end: NaiveDate) -> Vec<DailyStress>;
+        fn daily_spo2_range(start: NaiveDate, end: NaiveDate) -> Vec<DailySpo2>;
+        fn daily_cardiovascular_age_range(start: NaiveDate, end: NaiveDate) -> Vec<DailyCardiovascularAge>;
+        fn daily_resilience_range(start: NaiveDate, end: NaiveDate) -> Vec<DailyResilience>;
+        fn vo2_max_range(start: NaiveDate, end: NaiveDate) -> Vec<Vo2Max>;
+        fn rest_mode_periods_range(start: NaiveDate, end: NaiveDate) -> Vec<RestModePeriod>;
+        fn sleep_time_range(start: NaiveDate, end: NaiveDate) -> Vec<SleepTime>;
+        fn workouts_range(start: NaiveDate, end: NaiveDate) -> Vec<Workout>;
+        fn sessions_range(start: NaiveDate, end: NaiveDate) -> Vec<Session>;
+        fn tags_range(start: NaiveDate, end: NaiveDate) -> Vec<Tag>;
+        fn enhanced_tags_range(start: NaiveDate, end: NaiveDate) -> Vec<EnhancedTag>;
+        fn heartrate_range(start: NaiveDate, end: NaiveDate) -> Vec<HeartRateSample>;
+        fn raw_range(endpoint: &str, start: NaiveDate, end: NaiveDate) -> Value;
+        fn raw(endpoint: &str, date: NaiveDate) -> Value;
     }
 }
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use oura_cli::analysis::Thresholds;
+use oura_cli::cache::Cache;
+use oura_cli::client::DEFAULT_BASE_URL;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::output::Format;
+
+/// Settings read from `~/.config/oura/config.toml` (or `$XDG_CONFIG_HOME/oura/config.toml`).
+///
+/// Any setting can also appear under `[profiles.NAME]`; the selected profile's
+/// values override the top-level ones, table by table.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// API host to send requests to, e.g. `http://127.0.0.1:8080` for a local mock.
+    pub base_url: Option<String>,
+    /// Output format when `--format` isn't given.
+    pub format: Option<Format>,
+    /// Where the default account's token comes from.
+    #[serde(flatten)]
+    pub credentials: TokenSettings,
+    /// Named accounts (`[accounts.NAME]`) for `--account` and `--all-accounts`.
+    pub accounts: BTreeMap<String, TokenSettings>,
+    /// Limits `analyze` flags against, and score color bands.
+    pub thresholds: Thresholds,
+    /// The OAuth application `oura auth login --oauth` signs in with.
+    pub oauth: OAuthConfig,
+    /// The profile that was applied, if any.
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+/// A token source in the config. `oura auth login` can store the token
+/// instead, so all of these may be left out.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TokenSettings {
+    /// Personal access token, inline.
+    pub token: Option<String>,
+    /// Environment variable holding the token, e.g. `ALEX_OURA_TOKEN`.
+    pub token_env: Option<String>,
+    /// File holding the token; `~/` is expanded.
+    pub token_file: Option<PathBuf>,
+}
+
+impl TokenSettings {
+    /// The token from `token_env`, then `token_file`, then `token`.
+    pub fn token(&self) -> Result<Option<String>> {
+        let from_env = |name: &str| std::env::var(name).ok().filter(|t| !t.is_empty());
+        if let Some(token) = self.token_env.as_deref().and_then(from_env) {
+            return Ok(Some(token));
+        }
+        if let Some(path) = &self.token_file {
+            let path = expand_home(path);
+            let token = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read token file {}", path.display()))?;
+            return Ok(Some(token.trim().to_string()));
+        }
+        Ok(self.token.clone())
+    }
+}
+
+/// `[oauth]`: an application registered at cloud.ouraring.com. The URLs
+/// only need changing to point at a stand-in authorization server.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct OAuthConfig {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    /// Must match the redirect URI registered for the application.
+    pub redirect_uri: Option<String>,
+    pub authorize_url: Option<String>,
+    pub token_url: Option<String>,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("oura").join("config.toml"))
+    }
+
+    /// Loads the config file with `profile` (or `OURA_PROFILE`, or the file's
+    /// `default_profile`) applied. A missing default file yields defaults; a
+    /// missing `--config` file is an error.
+    pub fn load(explicit: Option<&Path>, profile: Option<&str>) -> Result<Self> {
+        let path = match explicit {
+            Some(path) => path.to_path_buf(),
+            None => match Self::path().filter(|p| p.exists()) {
+                Some(path) => path,
+                None => return Ok(Self::default()),
+            },
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut table: toml::Table = toml::from_str(&text)
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+        let profiles = match table.remove("profiles") {
+            Some(toml::Value::Table(profiles)) => profiles,
+            Some(_) => bail!(
+                "Invalid config file {}: `profiles` must be a table",
+                path.display()
+            ),
+            None => toml::Table::new(),
+        };
+        let default_profile = table.remove("default_profile");
+        let env_profile = std::env::var("OURA_PROFILE").ok().filter(|p| !p.is_empty());
+        let selected = profile
+            .map(str::to_string)
+            .or(env_profile)
+            .or_else(|| default_profile.and_then(|p| p.as_str().map(str::to_string)));
+
+        if let Some(name) = &selected {
+            match profiles.get(name) {
+                Some(toml::Value::Table(overrides)) => merge(&mut table, overrides),
+                _ => {
+                    let known: Vec<_> = profiles.keys().map(String::as_str).collect();
+                    bail!(
+                        "No profile `{name}` in {} (available: {})",
+                        path.display(),
+                        if known.is_empty() {
+                            "none".to_string()
+                        } else {
+                            known.join(", ")
+                        }
+                    );
+                }
+            }
+        }
+
+        let config: Self = table
+            .try_into()
+            .with_context(|| format!("Invalid config file {}", path.display()))?;
+        Ok(Self {
+            profile: selected,
+            ..config
+        })
+    }
+
+    /// The token settings for `[accounts.NAME]`.
+    pub fn account(&self, name: &str) -> Result<&TokenSettings> {
+        self.accounts.get(name).with_context(|| {
+            let known: Vec<_> = self.accounts.keys().map(String::as_str).collect();
+            format!(
+                "No account `{name}` in the config (available: {}); add an [accounts.{name}] table",
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )
+        })
+    }
+
+    /// The cache database for this profile or named account, API host and
+    /// credential (`owner`, a hex digest), so records fetched for someone
+    /// else or from a mock server are never served from it.
+    pub fn cache_path(&self, account: Option<&str>, owner: &str) -> Option<PathBuf> {
+        let base_url = self.base_url();
+        let base_url = base_url.as_deref().unwrap_or(DEFAULT_BASE_URL);
+        let digest = Sha256::digest(format!("{base_url}\0{owner}").as_bytes());
+        let id: String = digest[..6].iter().map(|b| format!("{b:02x}")).collect();
+        let path = Cache::default_path()?;
+        Some(match (account, &self.profile) {
+            (Some(name), _) => path.with_file_name(format!("cache-account-{name}-{id}.sqlite")),
+            (None, Some(name)) => path.with_file_name(format!("cache-{name}-{id}.sqlite")),
+            (None, None) => path.with_file_name(format!("cache-{id}.sqlite")),
+        })
+    }
+
+    /// `OURA_BASE_URL` takes precedence over the config file.
+    pub fn base_url(&self) -> Option<String> {
+        std::env::var("OURA_BASE_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .or_else(|| self.base_url.clone())
+    }
+}
+
+/// Overlays `overrides` onto `base`, merging nested tables key by key.
+fn merge(base: &mut toml::Table, overrides: &toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(key), value) {
+            (Some(toml::Value::Table(inner)), toml::Value::Table(value)) => merge(inner, value),
+            _ => {
+                base.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::{Result, bail};
+use chrono::{Datelike, Days, Local, Months, NaiveDate, Weekday};
+use clap::Args;
+use oura_cli::OuraError;
+
+/// A single day or a `--from/--to` / `--last` range, shared by the per-day commands.
+#[derive(Args, Default)]
+pub struct DateArgs {
+    /// Date or period: YYYY-MM-DD, "yesterday", "3 days ago", -3, "last monday", "2024-W07", "last week", ...
+    #[arg(allow_negative_numbers = true)]
+    pub date: Option<String>,
+    /// First day of a range
+    #[arg(long, allow_negative_numbers = true, conflicts_with_all = ["date", "last"])]
+    pub from: Option<String>,
+    /// Last day of a range (default: today)
+    #[arg(long, allow_negative_numbers = true, conflicts_with = "date")]
+    pub to: Option<String>,
+    /// Range ending today (or --to), e.g. 14d, 2w, 7
+    #[arg(long, conflicts_with_all = ["date", "from"])]
+    pub last: Option<String>,
+}
+
+impl DateArgs {
+    /// Inclusive (start, end) days. A single date is a one-day range.
+    pub fn resolve(&self) -> Result<(NaiveDate, NaiveDate)> {
+        if self.from.is_none() && self.to.is_none() && self.last.is_none() {
+            return Ok(resolve_range(self.date.as_deref())?);
+        }
+
+        let (_, end) = resolve_range(self.to.as_deref())?;
+        let start = match (&self.from, &self.last) {
+            (Some(from), _) => resolve_range(Some(from))?.0,
+            (None, Some(last)) => span_start(end, parse_span(last)?)?,
+            (None, None) => end,
+        };
+        if start > end {
+            bail!("--from {start} is after --to {end}");
+        }
+        Ok((start, end))
+    }
+}
+
+/// A single day. Periods like "last week" are rejected.
+pub fn resolve_date(input: Option<&str>) -> Result<NaiveDate> {
+    let (start, end) = resolve_range(input)?;
+    if start != end {
+        bail!(
+            "{:?} is a period ({start} → {end}); this command takes a single day",
+            input.unwrap_or_default()
+        );
+    }
+    Ok(start)
+}
+
+/// The inclusive days `input` refers to; a plain date is a one-day range.
+/// `None` means today.
+pub fn resolve_range(input: Option<&str>) -> Result<(NaiveDate, NaiveDate), OuraError> {
+    let today = Local::now().date_naive();
+    let Some(input) = input else {
+        return Ok((today, today));
+    };
+    let (start, end) =
+        parse_period(input, today).ok_or_else(|| OuraError::InvalidDate(input.to_string()))?;
+    // Days after today have no data yet.
+    Ok((start, if start <= today { end.min(today) } else { end }))
+}
+
+fn parse_period(input: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let s = input.trim().to_lowercase();
+    let day = |d: NaiveDate| Some((d, d));
+    let days_ago = |n: u64| today.checked_sub_days(Days::new(n));
+
+    match s.as_str() {
+        "today" => return day(today),
+        "yesterday" => return day(days_ago(1)?),
+        "this week" => return Some((week_start(today), today)),
+        "last week" => {
+            let start = week_start(today).checked_sub_days(Days::new(7))?;
+            return Some((start, start.checked_add_days(Days::new(6))?));
+        }
+        "this month" => return Some((today.with_day(1)?, today)),
+        "last month" => {
+            let end = today.with_day(1)?.pred_opt()?;
+            return Some((end.with_day(1)?, end));
+        }
+        _ => {}
+    }
+
+    if let Ok(d) = NaiveDate::parse_from_str(&s, "%Y-%m-%d") {
+        return day(d);
+    }
+
+    // "-2" → two days ago
+    if let Some(n) = s.strip_prefix('-').and_then(|n| n.parse().ok()) {
+        return day(days_ago(n)?);
+    }
+
+    // "3 days ago", "1 week ago"
+    if let Some(rest) = s.strip_suffix(" ago") {
+        let (n, unit) = rest.split_once(' ')?;
+        let n: u64 = n.parse().ok()?;
+        let per = match unit {
+            "day" | "days" => 1,
+            "week" | "weeks" => 7,
+            _ => return None,
+        };
+        return day(days_ago(n.checked_mul(per)?)?);
+    }
+
+    // "monday" is the most recent one (today included); "last monday" is strictly before today.
+    let (strict, name) = match s.strip_prefix("last ") {
+        Some(name) => (true, name),
+        None => (false, s.as_str()),
+    };
+    if let Ok(weekday) = name.parse::<Weekday>() {
+        let mut back =
+            (today.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+        if back == 0 && strict {
+            back = 7;
+        }
+        return day(days_ago(back as u64)?);
+    }
+
+    // ISO week "2024-W07" → Monday..Sunday
+    if let Some((year, week)) = s.split_once("-w") {
+        let start =
+            NaiveDate::from_isoywd_opt(year.parse().ok()?, week.parse().ok()?, Weekday::Mon)?;
+        return Some((start, start.checked_add_days(Days::new(6))?));
+    }
+
+    // Month "2024-02"
+    let start = NaiveDate::parse_from_str(&format!("{s}-01"), "%Y-%m-%d").ok()?;
+    let end = start.checked_add_months(Months::new(1))?.pred_opt()?;
+    Some((start, end))
+}
+
+fn week_start(d: NaiveDate) -> NaiveDate {
+    d - Days::new(d.weekday().num_days_from_monday() as u64)
+}
+
+/// "14d", "2w" or a bare "7" → number of days.
+fn parse_span(input: &str) -> Result<u64, OuraError> {
+    let s = input.trim().to_lowercase();
+    let (num, per) = match s.strip_suffix('w') {
+        Some(n) => (n, 7),
+        None => (s.strip_suffix('d').unwrap_or(&s), 1),
+    };
+    match num.parse::<u64>().ok().and_then(|n| n.checked_mul(per)) {
+        Some(n) if n > 0 => Ok(n),
+        _ => Err(OuraError::InvalidSpan(format!(
+            "{input:?} is not a number of days, expected e.g. 14d or 2w"
+        ))),
+    }
+}
+
+/// First day of the `n` days ending at `end`.
+pub fn span_start(end: NaiveDate, n: impl Into<u64>) -> Result<NaiveDate, OuraError> {
+    let n = n.into();
+    end.checked_sub_days(Days::new(n.max(1) - 1))
+        .ok_or_else(|| {
+            OuraError::InvalidSpan(format!(
+                "{n} days ending {end} reach back before the earliest supported date"
+            ))
+        })
+}
+
+/// Every day in `start..=end`.
+pub fn days(start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+    start.iter_days().take_while(|d| *d <= end).collect()
+}
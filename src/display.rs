@@ -1,28 +1,30 @@
 use owo_colors::OwoColorize;
 
-use crate::models::{DailyActivity, DailyReadiness, DailySleep, DailyStress, Sleep};
-use chrono::{DateTime, Duration, FixedOffset};
-
-pub fn colored_score(score: i64) -> String {
-    if score >= 85 {
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate, Timelike};
+use oura_cli::analysis::{
+    Analysis, Bedtime, Fitness, Severity, TagEvent, Thresholds, before_after, bpm_slots,
+    format_duration, heart_rate_by_source, primary_sleep, session_duration, workout_duration,
+    workout_totals,
+};
+use oura_cli::models::{
+    Contributors, DailyActivity, DailyReadiness, DailySleep, DailySpo2, DailyStress,
+    HeartRateSample, PersonalInfo, RingConfiguration, Session, Sleep, SleepPhase, TimeSeries,
+    Workout,
+};
+
+use crate::output::{AccountRow, ScoresRow, Trend, TrendRow};
+
+/// `score` colored by the `good_score` / `fair_score` bands.
+pub fn colored_score(score: i64, thresholds: &Thresholds) -> String {
+    if score >= thresholds.good_score {
         format!("{}", score.green())
-    } else if score >= 70 {
+    } else if score >= thresholds.fair_score {
         format!("{}", score.yellow())
     } else {
         format!("{}", score.red())
     }
 }
 
-pub fn format_duration(seconds: i64) -> String {
-    let hours = seconds / 3600;
-    let mins = (seconds % 3600) / 60;
-    if hours > 0 {
-        format!("{hours}h {mins:02}m")
-    } else {
-        format!("{mins}m")
-    }
-}
-
 fn format_time(iso: &str) -> &str {
     // "2024-02-13T23:15:00+08:00" → "23:15"
     if let Some(t) = iso.find('T') {
@@ -41,7 +43,8 @@ fn format_percent(part: i64, total: i64) -> String {
     format!("{}%", (part as f64 / total as f64 * 100.0).round() as i64)
 }
 
-fn format_contributor_key(key: &str) -> String {
+/// Turns an API identifier like `sleep_hrv` into a label like `Sleep HRV`.
+fn title_case(key: &str) -> String {
     key.split('_')
         .map(|w| match w {
             "hrv" | "hr" | "spo2" => w.to_uppercase(),
@@ -61,16 +64,14 @@ fn format_contributor_key(key: &str) -> String {
         .join(" ")
 }
 
-fn display_contributors(contributors: &serde_json::Value) {
-    if let Some(obj) = contributors.as_object() {
-        for (key, value) in obj {
-            if let Some(score) = value.as_i64() {
-                println!(
-                    "  {:<24}{}",
-                    format_contributor_key(key),
-                    colored_score(score)
-                );
-            }
+fn display_contributors(contributors: &impl Contributors, thresholds: &Thresholds) {
+    for (key, value) in contributors.scores() {
+        if let Some(score) = value {
+            println!(
+                "  {:<24}{}",
+                title_case(key),
+                colored_score(score, thresholds)
+            );
         }
     }
 }
@@ -81,45 +82,44 @@ pub fn display_scores(
     daily_sleep: Option<&DailySleep>,
     daily_readiness: Option<&DailyReadiness>,
     daily_activity: Option<&DailyActivity>,
+    thresholds: &Thresholds,
 ) {
     let s = daily_sleep
         .and_then(|d| d.score)
-        .map_or("--".dimmed().to_string(), colored_score);
+        .map_or("--".dimmed().to_string(), |v| colored_score(v, thresholds));
     let r = daily_readiness
         .and_then(|d| d.score)
-        .map_or("--".dimmed().to_string(), colored_score);
+        .map_or("--".dimmed().to_string(), |v| colored_score(v, thresholds));
     let a = daily_activity
         .and_then(|d| d.score)
-        .map_or("--".dimmed().to_string(), colored_score);
+        .map_or("--".dimmed().to_string(), |v| colored_score(v, thresholds));
     println!("  Sleep {s}  Readiness {r}  Activity {a}");
 
     // Show readiness contributors (the most actionable breakdown)
-    if let Some(ref c) = daily_readiness.and_then(|d| d.contributors.clone()) {
+    if let Some(c) = daily_readiness.and_then(|d| d.contributors.as_ref()) {
         println!();
         println!("  {}", "Readiness contributors:".dimmed());
-        display_contributors(c);
+        display_contributors(c, thresholds);
     }
 
     // Show temperature deviation if notable
-    if let Some(temp) = daily_readiness.and_then(|d| d.temperature_deviation) {
-        if temp.abs() >= 0.5 {
-            println!("  Temp Deviation:  {temp:+.1}°C");
-        }
+    if let Some(temp) = daily_readiness
+        .and_then(|d| d.temperature_deviation)
+        .filter(|temp| temp.abs() >= thresholds.max_temp_deviation)
+    {
+        println!("  Temp Deviation:  {temp:+.1}°C");
     }
 }
 
-pub fn display_sleep(daily: Option<&DailySleep>, records: &[Sleep]) {
-    let sleep = records
-        .iter()
-        .find(|s| s.sleep_type.as_deref() == Some("long_sleep"))
-        .or(records.first());
+pub fn display_sleep(daily: Option<&DailySleep>, records: &[Sleep], thresholds: &Thresholds) {
+    let sleep = primary_sleep(records);
 
     let score = daily.and_then(|d| d.score);
 
     match sleep {
         Some(s) => {
             if let Some(v) = score {
-                println!("  Sleep Score: {}", colored_score(v));
+                println!("  Sleep Score: {}", colored_score(v, thresholds));
             }
 
             let total = s.total_sleep_duration.unwrap_or(0);
@@ -173,10 +173,10 @@ pub fn display_sleep(daily: Option<&DailySleep>, records: &[Sleep]) {
             // No period data yet — show score + contributors from daily_sleep
             if let Some(d) = daily {
                 if let Some(v) = d.score {
-                    println!("  Sleep Score: {}", colored_score(v));
+                    println!("  Sleep Score: {}", colored_score(v, thresholds));
                 }
                 if let Some(ref c) = d.contributors {
-                    display_contributors(c);
+                    display_contributors(c, thresholds);
                 }
                 println!("  {}", "(detailed breakdown not yet synced)".dimmed());
             } else {
@@ -186,34 +186,20 @@ pub fn display_sleep(daily: Option<&DailySleep>, records: &[Sleep]) {
     }
 }
 
-pub fn display_analyze(
-    daily_sleep: Option<&DailySleep>,
-    daily_readiness: Option<&DailyReadiness>,
-    records: &[Sleep],
-) {
-    let sleep = records
-        .iter()
-        .find(|s| s.sleep_type.as_deref() == Some("long_sleep"))
-        .or(records.first());
-
-    let day = daily_sleep
-        .map(|d| d.day.as_str())
-        .or_else(|| daily_readiness.map(|r| r.day.as_str()))
-        .or_else(|| sleep.map(|s| s.day.as_str()))
-        .unwrap_or("--");
-
+pub fn display_analyze(analysis: &Analysis, thresholds: &Thresholds) {
+    let day = analysis.day.as_deref().unwrap_or("--");
     println!("  {}", format!("Analysis — {day}").dimmed());
 
-    let Some(s) = sleep else {
+    if !analysis.has_sleep {
         println!("  No sleep data");
         return;
-    };
+    }
 
-    if let Some(score) = daily_sleep.and_then(|d| d.score) {
-        println!("  Sleep Score: {}", colored_score(score));
+    if let Some(score) = analysis.sleep_score {
+        println!("  Sleep Score: {}", colored_score(score, thresholds));
     }
 
-    if let (Some(start), Some(end)) = (&s.bedtime_start, &s.bedtime_end) {
+    if let (Some(start), Some(end)) = (&analysis.bedtime_start, &analysis.bedtime_end) {
         println!(
             "  Bedtime:     {} → {}",
             format_time(start),
@@ -221,86 +207,34 @@ pub fn display_analyze(
         );
     }
 
-    let mut found_issues = false;
-
-    let wake_runs = wake_episodes(s);
-    if !wake_runs.is_empty() {
-        found_issues = true;
-        for (time, minutes) in &wake_runs {
-            println!("  {}", format!("Woke at {time} for {minutes} min").yellow());
-        }
-
-        if let Some((time, minutes)) = wake_runs.iter().max_by_key(|(_, minutes)| *minutes) {
-            println!(
-                "  {}",
-                format!("Longest wake: {time} ({minutes} min)").yellow()
-            );
-        }
-    }
-
-    if let Some(deep) = s.deep_sleep_duration.filter(|deep| *deep < 3600) {
-        found_issues = true;
-        println!(
-            "  {}",
-            format!("Low deep sleep: {}", format_duration(deep)).red()
-        );
-    }
-    if let Some(rem) = s.rem_sleep_duration.filter(|rem| *rem < 3600) {
-        found_issues = true;
-        println!("  {}", format!("Low REM: {}", format_duration(rem)).red());
-    }
-    if let Some(efficiency) = s.efficiency.filter(|eff| *eff < 80) {
-        found_issues = true;
-        println!("  {}", format!("Low efficiency: {efficiency}%").red());
-    }
-    if let Some(restless) = s.restless_periods.filter(|periods| *periods > 20) {
-        found_issues = true;
-        println!(
-            "  {}",
-            format!("High restlessness: {restless} periods").red()
-        );
-    }
-
-    if let Some(temp) = daily_readiness.and_then(|r| r.temperature_deviation) {
-        if temp >= 0.5 {
-            found_issues = true;
-            println!(
-                "  {}",
-                format!("Temp elevated: {temp:+.1}°C — possible illness or stress").red()
-            );
-        } else if temp <= -0.5 {
-            found_issues = true;
-            println!("  {}", format!("Temp depressed: {temp:+.1}°C").red());
-        }
-    }
-
-    if let Some(delta) = s.sleep_score_delta {
-        if delta > 5 {
+    for flag in &analysis.flags {
+        let line = match flag.severity {
+            Severity::Notice => flag.message.yellow().to_string(),
+            Severity::Issue => flag.message.red().to_string(),
+            Severity::Positive => flag.message.green().to_string(),
+        };
+        if flag.notes.is_empty() {
+            println!("  {line}");
+        } else {
             println!(
-                "  {}",
-                format!("Better than your baseline (+{delta} pts)").green()
+                "  {line}  {}",
+                format!("({})", flag.notes.join(", ")).dimmed()
             );
-        } else if delta < -5 {
-            found_issues = true;
-            println!("  {}", format!("Below your baseline ({delta} pts)").red());
         }
     }
 
-    if !found_issues {
+    if analysis.is_clean() {
         println!("  Sleep looks clean — no significant flags.");
     }
 }
 
-pub fn display_hypnogram(daily: Option<&DailySleep>, records: &[Sleep]) {
-    let sleep = records
-        .iter()
-        .find(|s| s.sleep_type.as_deref() == Some("long_sleep"))
-        .or(records.first());
+pub fn display_hypnogram(daily: Option<&DailySleep>, records: &[Sleep], thresholds: &Thresholds) {
+    let sleep = primary_sleep(records);
 
     let Some(s) = sleep else {
         if let Some(d) = daily {
             if let Some(v) = d.score {
-                println!("  Sleep Score: {}", colored_score(v));
+                println!("  Sleep Score: {}", colored_score(v, thresholds));
             }
             println!("  {}", "(hypnogram not yet synced)".dimmed());
         } else {
@@ -314,35 +248,26 @@ pub fn display_hypnogram(daily: Option<&DailySleep>, records: &[Sleep]) {
     }
 
     if let Some(v) = daily.and_then(|d| d.score) {
-        println!("  Sleep Score: {}", colored_score(v));
+        println!("  Sleep Score: {}", colored_score(v, thresholds));
     }
 
-    let Some(phases) = s.sleep_phase_5_min.as_deref() else {
+    let phases: Vec<_> = s.phases().collect();
+    let Some(&(start, _)) = phases.first() else {
         println!("  {}", "(hypnogram not yet synced)".dimmed());
         return;
     };
 
-    if phases.is_empty() {
-        println!("  {}", "(hypnogram not yet synced)".dimmed());
-        return;
-    }
-
-    let step = if phases.chars().count() > 120 { 2 } else { 1 };
+    let step = if phases.len() > 120 { 2 } else { 1 };
 
     let mut bar = String::new();
     let mut width = 0usize;
-    for (idx, phase) in phases.chars().enumerate() {
-        if idx % step != 0 {
-            continue;
-        }
-
+    for (_, phase) in phases.iter().step_by(step) {
         let segment = match phase {
-            '1' => format!("{}", "█".blue()),
-            '2' => format!("{}", "█".green()),
-            '3' => format!("{}", "█".magenta()),
-            '4' => format!("{}", "█".yellow()),
-            '0' => format!("{}", "·".dimmed()),
-            _ => format!("{}", "·".dimmed()),
+            SleepPhase::Deep => format!("{}", "█".blue()),
+            SleepPhase::Light => format!("{}", "█".green()),
+            SleepPhase::Rem => format!("{}", "█".magenta()),
+            SleepPhase::Awake => format!("{}", "█".yellow()),
+            SleepPhase::Unknown => format!("{}", "·".dimmed()),
         };
         bar.push_str(&segment);
         width += 1;
@@ -350,11 +275,9 @@ pub fn display_hypnogram(daily: Option<&DailySleep>, records: &[Sleep]) {
 
     println!("  {bar}");
 
-    if let Some(start_hour) = hypnogram_start_hour(s.bedtime_start.as_deref()) {
-        let axis = build_hypnogram_axis(width, step, start_hour);
-        if !axis.trim().is_empty() {
-            println!("  {}", axis.dimmed());
-        }
+    let axis = time_axis(start, 5 * step as i64, width);
+    if !axis.trim().is_empty() {
+        println!("  {}", axis.dimmed());
     }
 
     println!(
@@ -366,14 +289,14 @@ pub fn display_hypnogram(daily: Option<&DailySleep>, records: &[Sleep]) {
     );
 }
 
-pub fn display_readiness(record: Option<&DailyReadiness>) {
+pub fn display_readiness(record: Option<&DailyReadiness>, thresholds: &Thresholds) {
     let Some(r) = record else {
         println!("  No readiness data");
         return;
     };
 
     if let Some(v) = r.score {
-        println!("  Readiness Score: {}", colored_score(v));
+        println!("  Readiness Score: {}", colored_score(v, thresholds));
     }
 
     if let Some(temp) = r.temperature_deviation {
@@ -381,18 +304,18 @@ pub fn display_readiness(record: Option<&DailyReadiness>) {
     }
 
     if let Some(ref c) = r.contributors {
-        display_contributors(c);
+        display_contributors(c, thresholds);
     }
 }
 
-pub fn display_activity(record: Option<&DailyActivity>) {
+pub fn display_activity(record: Option<&DailyActivity>, thresholds: &Thresholds) {
     let Some(a) = record else {
         println!("  No activity data");
         return;
     };
 
     if let Some(v) = a.score {
-        println!("  Activity Score: {}", colored_score(v));
+        println!("  Activity Score: {}", colored_score(v, thresholds));
     }
 
     if let Some(steps) = a.steps {
@@ -423,11 +346,174 @@ pub fn display_activity(record: Option<&DailyActivity>) {
     }
 }
 
-pub fn display_hrv(daily: Option<&DailySleep>, records: &[Sleep]) {
-    let sleep = records
-        .iter()
-        .find(|s| s.sleep_type.as_deref() == Some("long_sleep"))
-        .or(records.first());
+fn format_distance(meters: f64) -> String {
+    if meters >= 1000.0 {
+        format!("{:.1} km", meters / 1000.0)
+    } else {
+        format!("{meters:.0} m")
+    }
+}
+
+/// Lists tags oldest first; spans show when they ended.
+pub fn display_tags(tags: &[TagEvent]) {
+    if tags.is_empty() {
+        println!("  No tags");
+        return;
+    }
+
+    println!(
+        "  {}",
+        format!("{:<12}{:<14}{:<20}{}", "Date", "Time", "Tag", "Comment").dimmed()
+    );
+    for tag in tags {
+        let date = day_label(&tag.day);
+        let time = match &tag.end {
+            Some(end) => format!("{}–{}", format_time(&tag.start), format_time(end)),
+            None => format_time(&tag.start).to_string(),
+        };
+        println!(
+            "  {date:<12}{time:<14}{:<20}{}",
+            tag.name,
+            tag.comment.as_deref().unwrap_or("").dimmed()
+        );
+    }
+}
+
+/// Lists workouts oldest first, then totals per activity type.
+pub fn display_workouts(workouts: &[Workout]) {
+    if workouts.is_empty() {
+        println!("  No workouts");
+        return;
+    }
+
+    let mut sorted: Vec<&Workout> = workouts.iter().collect();
+    sorted.sort_by(|a, b| a.start_datetime.cmp(&b.start_datetime));
+
+    println!(
+        "  {}",
+        format!(
+            "{:<12}{:<7}{:<20}{:<10}{:>9}{:>10}{:>10}  {}",
+            "Date", "Start", "Activity", "Intensity", "Duration", "Calories", "Distance", "Source"
+        )
+        .dimmed()
+    );
+    for w in sorted {
+        let date = day_label(&w.day);
+        let intensity = w.intensity.as_deref().unwrap_or("--");
+        let intensity = match intensity {
+            "easy" => format!("{intensity:<10}").green().to_string(),
+            "moderate" => format!("{intensity:<10}").yellow().to_string(),
+            "hard" => format!("{intensity:<10}").red().to_string(),
+            _ => format!("{intensity:<10}"),
+        };
+        println!(
+            "  {date:<12}{:<7}{:<20}{intensity}{:>9}{:>10}{:>10}  {}",
+            format_time(&w.start_datetime),
+            title_case(w.label.as_deref().unwrap_or(&w.activity)),
+            workout_duration(w).map_or("--".to_string(), format_duration),
+            w.calories
+                .map_or("--".to_string(), |c| format!("{c:.0} kcal")),
+            w.distance.map_or("--".to_string(), format_distance),
+            w.source.as_deref().unwrap_or("--").dimmed()
+        );
+    }
+
+    println!();
+    println!("  {}", "Totals".dimmed());
+    for t in workout_totals(workouts) {
+        let mut line = format!(
+            "  {:<20}{:>3} × {:>8}{:>10}",
+            title_case(&t.activity),
+            t.count,
+            format_duration(t.duration),
+            format!("{:.0} kcal", t.calories)
+        );
+        if t.distance > 0.0 {
+            line.push_str(&format!("{:>10}", format_distance(t.distance)));
+        }
+        println!("{line}");
+    }
+}
+
+/// Lists sessions oldest first. With `detail` (or a single session), also
+/// charts each session's heart rate and HRV with start-vs-end averages.
+pub fn display_sessions(sessions: &[Session], detail: bool) {
+    if sessions.is_empty() {
+        println!("  No sessions");
+        return;
+    }
+
+    let mut sorted: Vec<&Session> = sessions.iter().collect();
+    sorted.sort_by(|a, b| a.start_datetime.cmp(&b.start_datetime));
+
+    println!(
+        "  {}",
+        format!(
+            "{:<12}{:<7}{:<12}{:>9}  {}",
+            "Date", "Start", "Type", "Duration", "Mood"
+        )
+        .dimmed()
+    );
+    for s in &sorted {
+        let date = day_label(&s.day);
+        let mood = s.mood.as_deref().unwrap_or("--");
+        let mood = match mood {
+            "great" | "good" => mood.green().to_string(),
+            "same" => mood.yellow().to_string(),
+            "worse" | "bad" => mood.red().to_string(),
+            _ => mood.dimmed().to_string(),
+        };
+        println!(
+            "  {date:<12}{:<7}{:<12}{:>9}  {mood}",
+            format_time(&s.start_datetime),
+            title_case(&s.session_type),
+            session_duration(s).map_or("--".to_string(), format_duration),
+        );
+    }
+
+    if !detail && sorted.len() > 1 {
+        return;
+    }
+    for s in sorted {
+        println!();
+        println!(
+            "  {} {} -> {}",
+            title_case(&s.session_type).bold(),
+            format_time(&s.start_datetime),
+            format_time(&s.end_datetime)
+        );
+        let mut charted = false;
+        if let Some(hr) = &s.heart_rate {
+            charted |= display_session_series("HR ", hr, "bpm");
+        }
+        if let Some(hrv) = &s.heart_rate_variability {
+            charted |= display_session_series("HRV", hrv, "ms");
+        }
+        if !charted {
+            println!("  {}", "(no heart rate recorded)".dimmed());
+        }
+    }
+}
+
+/// One sparkline row with the first-minute and last-minute averages.
+/// Returns false when the series has no readings.
+fn display_session_series(label: &str, series: &TimeSeries, unit: &str) -> bool {
+    let Some(change) = before_after(series) else {
+        return false;
+    };
+    let (bar, _, _) = sparkline(&series.items, SPARKLINE_WIDTH);
+    println!(
+        "  {}  {}  {:.0} → {:.0} {unit}",
+        label.dimmed(),
+        bar.red(),
+        change.before,
+        change.after
+    );
+    true
+}
+
+pub fn display_hrv(daily: Option<&DailySleep>, records: &[Sleep], thresholds: &Thresholds) {
+    let sleep = primary_sleep(records);
 
     match sleep {
         Some(s) => {
@@ -454,7 +540,7 @@ pub fn display_hrv(daily: Option<&DailySleep>, records: &[Sleep]) {
                 if let Some(v) = d.score {
                     println!(
                         "  Sleep Score: {} {}",
-                        colored_score(v),
+                        colored_score(v, thresholds),
                         "(HRV requires detailed sync)".dimmed()
                     );
                 }
@@ -465,6 +551,210 @@ pub fn display_hrv(daily: Option<&DailySleep>, records: &[Sleep]) {
     }
 }
 
+pub fn display_spo2(record: Option<&DailySpo2>, thresholds: &Thresholds) {
+    let Some(s) = record else {
+        println!("  No SpO2 data");
+        return;
+    };
+
+    match s.average() {
+        Some(spo2) if spo2 < thresholds.min_spo2 => {
+            println!("  Avg SpO2:        {}", format!("{spo2:.1}%").red())
+        }
+        Some(spo2) => println!("  Avg SpO2:        {}", format!("{spo2:.1}%").green()),
+        None => println!("  Avg SpO2:        --"),
+    }
+
+    if let Some(bdi) = s.breathing_disturbance_index {
+        let rate = format!("{bdi}/h");
+        if bdi > thresholds.max_bdi {
+            println!("  Breathing Index: {}", rate.red());
+        } else {
+            println!("  Breathing Index: {rate}");
+        }
+    }
+}
+
+pub fn display_fitness(fitness: &Fitness, window: u64, thresholds: &Thresholds) {
+    let as_of = |day: &str| -> String {
+        if day == fitness.day {
+            return String::new();
+        }
+        let label = NaiveDate::parse_from_str(day, "%Y-%m-%d")
+            .map(|d| d.format("%b %d").to_string())
+            .unwrap_or_else(|_| day.to_string());
+        format!(" {}", format!("(as of {label})").dimmed())
+    };
+
+    match &fitness.vascular_age {
+        Some(age) => println!("  Vascular Age:  {}{}", age.value, as_of(&age.day)),
+        None => println!("  Vascular Age:  --"),
+    }
+
+    match &fitness.resilience {
+        Some(r) => {
+            let level = r.level.as_deref().unwrap_or("--");
+            let colored = match level {
+                "exceptional" | "strong" => level.green().to_string(),
+                "solid" | "adequate" => level.yellow().to_string(),
+                "limited" => level.red().to_string(),
+                _ => level.to_string(),
+            };
+            println!("  Resilience:    {colored}{}", as_of(&r.day));
+            for (key, value) in [
+                ("Sleep Recovery", r.sleep_recovery),
+                ("Daytime Recovery", r.daytime_recovery),
+                ("Stress", r.stress),
+            ] {
+                if let Some(v) = value {
+                    println!(
+                        "    {key:<22}{}",
+                        colored_score(v.round() as i64, thresholds)
+                    );
+                }
+            }
+        }
+        None => println!("  Resilience:    --"),
+    }
+
+    let vo2 = &fitness.vo2_max;
+    match &vo2.latest {
+        Some(latest) => {
+            let change = vo2.change.map_or(String::new(), |c| {
+                format!(" {}", format!("({c:+.1} over {window} days)").dimmed())
+            });
+            println!(
+                "  VO2 Max:       {:.1} ml/kg/min{change}{}",
+                latest.value,
+                as_of(&latest.day)
+            );
+            if vo2.history.len() > 1 {
+                let values: Vec<Option<f64>> = vo2.history.iter().map(|r| Some(r.value)).collect();
+                let (bar, _, _) = sparkline(&values, SPARKLINE_WIDTH);
+                println!("                 {}", bar.cyan());
+            }
+        }
+        None => println!("  VO2 Max:       --"),
+    }
+}
+
+pub fn display_whoami(info: &PersonalInfo) {
+    println!("  Email:   {}", info.email.as_deref().unwrap_or("--"));
+    if let Some(id) = &info.id {
+        println!("  ID:      {}", id.dimmed());
+    }
+    if let Some(age) = info.age {
+        println!("  Age:     {age}");
+    }
+    if let Some(sex) = &info.biological_sex {
+        println!("  Sex:     {sex}");
+    }
+    if let Some(height) = info.height {
+        println!("  Height:  {height:.2} m");
+    }
+    if let Some(weight) = info.weight {
+        println!("  Weight:  {weight:.1} kg");
+    }
+}
+
+/// Rings newest first.
+pub fn display_rings(rings: &[RingConfiguration]) {
+    if rings.is_empty() {
+        println!("  No rings registered");
+        return;
+    }
+
+    let mut sorted: Vec<&RingConfiguration> = rings.iter().collect();
+    sorted.sort_by(|a, b| b.set_up_at.cmp(&a.set_up_at));
+    for (idx, ring) in sorted.into_iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+        let mut title: Vec<String> = [&ring.hardware_type, &ring.design, &ring.color]
+            .into_iter()
+            .flatten()
+            .map(|s| title_case(s))
+            .collect();
+        if let Some(size) = ring.size {
+            title.push(format!("size {size}"));
+        }
+        println!("  {}", title.join(" · ").bold());
+        if let Some(firmware) = &ring.firmware_version {
+            println!("  Firmware:  {firmware}");
+        }
+        if let Some(set_up) = &ring.set_up_at {
+            println!("  Set up:    {}", set_up.get(..10).unwrap_or(set_up));
+        }
+    }
+}
+
+fn describe_bedtime_status(status: &str) -> String {
+    match status {
+        "optimal_found" => "based on your recent nights".to_string(),
+        "only_recommended_found" => {
+            "general recommendation; not enough good nights for yours".to_string()
+        }
+        "not_enough_nights" | "not_enough_recent_nights" => {
+            "not enough recent nights for a recommendation".to_string()
+        }
+        "bad_sleep_quality" => "recent sleep too poor for a recommendation".to_string(),
+        other => other.replace('_', " "),
+    }
+}
+
+fn describe_bedtime_recommendation(recommendation: &str) -> String {
+    match recommendation {
+        "earlier_bedtime" => "Try going to bed earlier".to_string(),
+        "later_bedtime" => "Try going to bed later".to_string(),
+        "earliest_bedtime" => "Aim for the start of the window".to_string(),
+        "latest_bedtime" => "Aim for the end of the window".to_string(),
+        "follow_optimal_bedtime" => "Keep to the window".to_string(),
+        "improve_efficiency" => "Improve sleep efficiency first".to_string(),
+        other => title_case(other),
+    }
+}
+
+pub fn display_bedtime(bedtime: &Bedtime) {
+    match (&bedtime.window_start, &bedtime.window_end) {
+        (Some(start), Some(end)) => {
+            println!("  Recommended: {}", format!("{start} – {end}").bold())
+        }
+        _ => println!("  Recommended: --"),
+    }
+    if let Some(status) = &bedtime.status {
+        println!("  {}", describe_bedtime_status(status).dimmed());
+    }
+    if let Some(recommendation) = &bedtime.recommendation {
+        println!("  {}", describe_bedtime_recommendation(recommendation));
+    }
+
+    if bedtime.nights.is_empty() {
+        return;
+    }
+    println!();
+    println!(
+        "  {}",
+        format!("{:<12}{:<9}{}", "Night", "Bedtime", "vs window").dimmed()
+    );
+    for night in &bedtime.nights {
+        let label = day_label(&night.day);
+        let verdict = match night.minutes_off {
+            Some(0) => "within".green().to_string(),
+            Some(m) if m < 0 => format!("{} early", format_duration(-m * 60))
+                .yellow()
+                .to_string(),
+            Some(m) => format!("{} late", format_duration(m * 60))
+                .red()
+                .to_string(),
+            None => "--".dimmed().to_string(),
+        };
+        println!(
+            "  {label:<12}{:<9}{verdict}",
+            format_time(&night.bedtime_start)
+        );
+    }
+}
+
 pub fn display_stress(record: Option<&DailyStress>) {
     let Some(s) = record else {
         println!("  No stress data");
@@ -489,178 +779,284 @@ pub fn display_stress(record: Option<&DailyStress>) {
     }
 }
 
-pub fn display_trend(
-    days: &[String],
-    sleep: &[crate::models::DailySleep],
-    readiness: &[crate::models::DailyReadiness],
-    activity: &[crate::models::DailyActivity],
-) {
-    use std::collections::HashMap;
-
-    let sleep_map: HashMap<&str, Option<i64>> =
-        sleep.iter().map(|s| (s.day.as_str(), s.score)).collect();
-    let readiness_map: HashMap<&str, Option<i64>> = readiness
-        .iter()
-        .map(|r| (r.day.as_str(), r.score))
-        .collect();
-    let activity_map: HashMap<&str, Option<i64>> =
-        activity.iter().map(|a| (a.day.as_str(), a.score)).collect();
+pub fn display_heartrate(samples: &[HeartRateSample]) {
+    let stats = heart_rate_by_source(samples);
+    if stats.is_empty() {
+        println!("  No heart rate data");
+        return;
+    }
 
     println!(
         "  {}",
         format!(
-            "{:<12}{:>7}{:>11}{:>10}",
-            "Date", "Sleep", "Readiness", "Activity"
+            "{:<10}{:>6}{:>6}{:>6}{:>9}",
+            "Source", "Min", "Avg", "Max", "Samples"
         )
         .dimmed()
     );
+    for s in &stats {
+        println!(
+            "  {:<10}{:>6}{:>6}{:>6}{:>9}",
+            s.source,
+            s.min,
+            s.average.round() as i64,
+            s.max,
+            s.samples
+        );
+    }
 
-    let mut sleep_sum: i64 = 0;
-    let mut sleep_count: i64 = 0;
-    let mut readiness_sum: i64 = 0;
-    let mut readiness_count: i64 = 0;
-    let mut activity_sum: i64 = 0;
-    let mut activity_count: i64 = 0;
-
-    for day in days {
-        let s = sleep_map.get(day.as_str()).copied().flatten();
-        let r = readiness_map.get(day.as_str()).copied().flatten();
-        let a = activity_map.get(day.as_str()).copied().flatten();
-
-        if let Some(v) = s {
-            sleep_sum += v;
-            sleep_count += 1;
-        }
-        if let Some(v) = r {
-            readiness_sum += v;
-            readiness_count += 1;
-        }
-        if let Some(v) = a {
-            activity_sum += v;
-            activity_count += 1;
-        }
+    let Some((start, slots)) = bpm_slots(samples, 5) else {
+        return;
+    };
+    let (bar, width, step) = sparkline(&slots, SPARKLINE_WIDTH);
+    println!();
+    println!("  {}", bar.red());
+    let axis = time_axis(
+        start.with_timezone(&Local).fixed_offset(),
+        5 * step as i64,
+        width,
+    );
+    if !axis.trim().is_empty() {
+        println!("  {}", axis.dimmed());
+    }
 
-        // Format date as "Mon Feb 10"
-        let label = chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d")
-            .map(|d| d.format("%a %b %d").to_string())
-            .unwrap_or_else(|_| day.clone());
+    let min = stats.iter().map(|s| s.min).min().unwrap_or_default();
+    let max = stats.iter().map(|s| s.max).max().unwrap_or_default();
+    println!("  {}", format!("{min}–{max} bpm").dimmed());
+}
 
-        let sc = s.map_or("  --".dimmed().to_string(), |v| {
-            format!("{:>4}", colored_score(v))
-        });
-        let rc = r.map_or("  --".dimmed().to_string(), |v| {
-            format!("{:>4}", colored_score(v))
-        });
-        let ac = a.map_or("  --".dimmed().to_string(), |v| {
-            format!("{:>4}", colored_score(v))
-        });
+pub fn display_trend(trend: &Trend, thresholds: &Thresholds) {
+    println!(
+        "  {}",
+        format!(
+            "{:<12}{:>7}{:>11}{:>10}{:>8}{:>6}",
+            "Date", "Sleep", "Readiness", "Activity", "SpO2", "BDI"
+        )
+        .dimmed()
+    );
 
-        println!("  {label:<12}   {sc}      {rc}     {ac}");
-    }
+    let spo2 = |row: &TrendRow| -> String {
+        let spo2 = row.spo2.map_or("--".to_string(), |v| format!("{v:.1}"));
+        let bdi = row.bdi.map_or("--".to_string(), |v| v.to_string());
+        format!("{spo2:>8}{bdi:>6}")
+    };
 
-    // Averages
-    let avg = |sum: i64, count: i64| -> String {
-        if count == 0 {
-            "  --".dimmed().to_string()
+    for row in &trend.days {
+        let rest = if row.rest_mode {
+            format!("  {}", "rest mode".yellow())
         } else {
-            format!("{:>4}", colored_score(sum / count))
-        }
-    };
+            String::new()
+        };
+        println!(
+            "  {:<12}{}{}{rest}",
+            day_label(&row.date),
+            score_cells(row.sleep, row.readiness, row.activity, thresholds),
+            spo2(row)
+        );
+    }
 
     println!(
-        "  {:<12}   {}      {}     {}",
-        "Average".dimmed(),
-        avg(sleep_sum, sleep_count),
-        avg(readiness_sum, readiness_count),
-        avg(activity_sum, activity_count),
+        "  {}{}{}",
+        format!("{:<12}", "Average").dimmed(),
+        score_cells(
+            trend.average.sleep,
+            trend.average.readiness,
+            trend.average.activity,
+            thresholds,
+        ),
+        spo2(&trend.average)
     );
 }
 
-fn format_number(n: i64) -> String {
-    if n >= 1000 {
-        format!("{},{:03}", n / 1000, n % 1000)
-    } else {
-        n.to_string()
+/// One day's scores for several accounts, one row each, in the trend's columns.
+pub fn display_account_scores(rows: &[AccountRow<&ScoresRow>], thresholds: &Thresholds) {
+    let width = rows
+        .iter()
+        .map(|r| r.account.chars().count() + 2)
+        .max()
+        .unwrap_or(0)
+        .max(12);
+    println!(
+        "  {}",
+        format!(
+            "{:<width$}{:>7}{:>11}{:>10}",
+            "Account", "Sleep", "Readiness", "Activity"
+        )
+        .dimmed()
+    );
+    for AccountRow { account, row } in rows {
+        println!(
+            "  {account:<width$}{}",
+            score_cells(
+                row.sleep_score,
+                row.readiness_score,
+                row.activity_score,
+                thresholds,
+            )
+        );
     }
 }
 
-fn wake_episodes(sleep: &Sleep) -> Vec<(String, i64)> {
-    let Some(phases) = sleep.sleep_phase_5_min.as_deref() else {
-        return Vec::new();
-    };
-    let Some(start) = parse_bedtime_start(sleep.bedtime_start.as_deref()) else {
-        return Vec::new();
-    };
+/// Trends for several accounts side by side: each account gets the
+/// trend's Sleep/Readiness/Activity columns under its name.
+pub fn display_trend_comparison(trends: &[AccountRow<&Trend>], thresholds: &Thresholds) {
+    // Sleep + Readiness + Activity columns.
+    const GROUP: usize = 7 + 11 + 10;
+    let names: String = trends
+        .iter()
+        .map(|t| {
+            let name: String = t.account.chars().take(GROUP - 2).collect();
+            format!("{name:>GROUP$}")
+        })
+        .collect();
+    println!("  {:<12}{}", "", names.bold());
+    println!(
+        "  {}",
+        format!(
+            "{:<12}{}",
+            "Date",
+            format!("{:>7}{:>11}{:>10}", "Sleep", "Readiness", "Activity").repeat(trends.len())
+        )
+        .dimmed()
+    );
 
-    let mut runs = Vec::new();
-    let mut run_start = None;
+    let Some(first) = trends.first() else {
+        return;
+    };
+    for (idx, day) in first.row.days.iter().enumerate() {
+        let rows: Vec<_> = trends.iter().map(|t| &t.row.days[idx]).collect();
+        let cells: String = rows
+            .iter()
+            .map(|r| score_cells(r.sleep, r.readiness, r.activity, thresholds))
+            .collect();
+        let resting: Vec<&str> = trends
+            .iter()
+            .zip(&rows)
+            .filter(|(_, r)| r.rest_mode)
+            .map(|(t, _)| t.account)
+            .collect();
+        let rest = if resting.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "  {}",
+                format!("rest mode: {}", resting.join(", ")).yellow()
+            )
+        };
+        println!("  {:<12}{cells}{rest}", day_label(&day.date));
+    }
 
-    for (idx, phase) in phases.chars().enumerate() {
-        if phase == '4' {
-            run_start.get_or_insert(idx);
-            continue;
-        }
+    let averages: String = trends
+        .iter()
+        .map(|t| {
+            let avg = &t.row.average;
+            score_cells(avg.sleep, avg.readiness, avg.activity, thresholds)
+        })
+        .collect();
+    println!("  {}{averages}", format!("{:<12}", "Average").dimmed());
+}
 
-        if let Some(start_idx) = run_start.take() {
-            let run_len = idx - start_idx;
-            if run_len >= 2 {
-                runs.push(format_wake_run(start, start_idx, run_len));
+/// Sleep, readiness and activity in the trend's 7/11/10-wide columns.
+fn score_cells(
+    sleep: Option<i64>,
+    readiness: Option<i64>,
+    activity: Option<i64>,
+    thresholds: &Thresholds,
+) -> String {
+    // Pad by hand: color codes would count towards `{:>width$}`.
+    let cell = |score: Option<i64>, width: usize| -> String {
+        match score {
+            Some(v) => {
+                let pad = width.saturating_sub(v.to_string().len());
+                format!("{:pad$}{}", "", colored_score(v, thresholds))
             }
+            None => format!("{:>width$}", "--").dimmed().to_string(),
         }
-    }
-
-    if let Some(start_idx) = run_start {
-        let run_len = phases.chars().count() - start_idx;
-        if run_len >= 2 {
-            runs.push(format_wake_run(start, start_idx, run_len));
-        }
-    }
-
-    runs
+    };
+    format!(
+        "{}{}{}",
+        cell(sleep, 7),
+        cell(readiness, 11),
+        cell(activity, 10)
+    )
 }
 
-fn parse_bedtime_start(iso: Option<&str>) -> Option<DateTime<FixedOffset>> {
-    iso.and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+/// "2024-02-10" as "Sat Feb 10".
+fn day_label(date: &str) -> String {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|d| d.format("%a %b %d").to_string())
+        .unwrap_or_else(|_| date.to_string())
 }
 
-fn format_wake_run(
-    bedtime_start: DateTime<FixedOffset>,
-    start_idx: usize,
-    run_len: usize,
-) -> (String, i64) {
-    let wake_time = bedtime_start + Duration::minutes((start_idx as i64) * 5);
-    let minutes = (run_len as i64) * 5;
-    (wake_time.format("%H:%M").to_string(), minutes)
+fn format_number(n: i64) -> String {
+    if n >= 1000 {
+        format!("{},{:03}", n / 1000, n % 1000)
+    } else {
+        n.to_string()
+    }
 }
 
-fn hypnogram_start_hour(iso: Option<&str>) -> Option<u32> {
-    let iso = iso?;
-    let t = iso.find('T')?;
-    let rest = &iso[t + 1..];
-    let hour = rest.get(..2)?;
-    hour.parse().ok()
+/// Widest sparkline before values are averaged into fewer columns.
+const SPARKLINE_WIDTH: usize = 72;
+
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A one-row bar chart scaled between the series' min and max, with gaps as
+/// spaces. Series longer than `max_width` are averaged down, `step` values per
+/// column. Returns the chart, its width and the step.
+fn sparkline(values: &[Option<f64>], max_width: usize) -> (String, usize, usize) {
+    let step = values.len().div_ceil(max_width).max(1);
+    let columns: Vec<Option<f64>> = values
+        .chunks(step)
+        .map(|chunk| {
+            let present: Vec<f64> = chunk.iter().flatten().copied().collect();
+            (!present.is_empty()).then(|| present.iter().sum::<f64>() / present.len() as f64)
+        })
+        .collect();
+
+    let present = columns.iter().flatten();
+    let min = present.clone().copied().fold(f64::INFINITY, f64::min);
+    let max = present.copied().fold(f64::NEG_INFINITY, f64::max);
+    let top = (SPARK_LEVELS.len() - 1) as f64;
+
+    let bar = columns
+        .iter()
+        .map(|value| match value {
+            Some(v) if max > min => SPARK_LEVELS[((v - min) / (max - min) * top).round() as usize],
+            Some(_) => SPARK_LEVELS[SPARK_LEVELS.len() / 2],
+            None => ' ',
+        })
+        .collect();
+    (bar, columns.len(), step)
 }
 
-fn build_hypnogram_axis(width: usize, step: usize, start_hour: u32) -> String {
-    let hour_step = 12 / step;
-    if hour_step == 0 || width == 0 {
+/// Hour labels under a chart whose columns are `minutes` wide, starting at
+/// `start` (labelled in its own offset). Labels are spaced far enough apart
+/// not to run together.
+fn time_axis(start: DateTime<FixedOffset>, minutes: i64, width: usize) -> String {
+    let Some(label_hours) = [1, 2, 3, 4, 6, 12]
+        .into_iter()
+        .find(|h| h * 60 >= minutes * 4)
+    else {
         return String::new();
-    }
+    };
 
+    let midnight = start - Duration::minutes(i64::from(start.hour() * 60 + start.minute()));
     let mut axis = vec![' '; width];
-    let mut pos = 0usize;
-    let mut hour = start_hour;
-
-    while pos < width {
-        let label = format!("{hour:02}");
-        for (offset, ch) in label.chars().enumerate() {
-            if pos + offset < width {
-                axis[pos + offset] = ch;
-            }
+    let mut last_label = None;
+    for col in 0..width {
+        let at = start + Duration::minutes(col as i64 * minutes);
+        let label = (at - midnight).num_hours() / label_hours;
+        if last_label.replace(label) == Some(label) || col + 2 > width {
+            continue;
+        }
+        if col == 0 && at.minute() != 0 {
+            continue;
+        }
+        let hour = format!("{:02}", (label * label_hours) % 24);
+        for (offset, ch) in hour.chars().enumerate() {
+            axis[col + offset] = ch;
         }
-        pos += hour_step;
-        hour = (hour + 1) % 24;
     }
-
     axis.into_iter().collect()
 }
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use thiserror::Error;
+
+/// Result alias used throughout the client.
+pub type Result<T> = std::result::Result<T, OuraError>;
+
+/// Everything that can go wrong talking to the Oura API.
+#[derive(Debug, Error)]
+pub enum OuraError {
+    /// `OuraClient::from_env` found no `OURA_TOKEN`.
+    #[error("OURA_TOKEN not set")]
+    MissingToken,
+    /// 401/403: the token is invalid, expired or lacks scope.
+    #[error("Oura API returned {status}: {body}")]
+    Unauthorized { status: StatusCode, body: String },
+    /// 429 after exhausting retries.
+    #[error("Oura API returned 429 Too Many Requests: {body}")]
+    RateLimited {
+        retry_after: Option<Duration>,
+        body: String,
+    },
+    #[error("Oura API returned 404 Not Found: {body}")]
+    NotFound { body: String },
+    /// 5xx after exhausting retries.
+    #[error("Oura API returned {status}: {body}")]
+    Server { status: StatusCode, body: String },
+    /// Any other non-success status (e.g. 400 for a malformed query).
+    #[error("Oura API returned {status}: {body}")]
+    Api { status: StatusCode, body: String },
+    #[error("Failed to reach Oura API")]
+    Network(#[from] reqwest::Error),
+    #[error("Failed to parse API response")]
+    Decode(#[from] serde_json::Error),
+    #[error("Invalid date {0:?}, expected YYYY-MM-DD")]
+    InvalidDate(String),
+    /// A `--last` span or day window that doesn't parse, or reaches back
+    /// before the earliest supported date.
+    #[error("Invalid span: {0}")]
+    InvalidSpan(String),
+    #[error("Local cache error")]
+    Cache(#[from] rusqlite::Error),
+    #[error("Local cache I/O error")]
+    Io(#[from] std::io::Error),
+    /// Offline mode was requested but there is no cache to read from.
+    #[error("Offline mode needs the local cache")]
+    Offline,
+    /// An operation like sync that stores data was attempted without a cache.
+    #[error("This command needs the local cache")]
+    NoCache,
+    /// `OuraClient` couldn't start the runtime its requests run on.
+    #[error("Failed to start the async runtime")]
+    Runtime(#[source] std::io::Error),
+    /// The OAuth2 authorization flow failed before any token was issued.
+    #[error("OAuth authorization failed: {0}")]
+    OAuth(String),
+}
+
+impl OuraError {
+    /// Classifies a non-success response.
+    pub fn from_status(status: StatusCode, retry_after: Option<Duration>, body: String) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Unauthorized { status, body },
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited { retry_after, body },
+            StatusCode::NOT_FOUND => Self::NotFound { body },
+            s if s.is_server_error() => Self::Server { status, body },
+            _ => Self::Api { status, body },
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! Unofficial client for the [Oura Ring](https://ouraring.com) API v2.
+//!
+//! ```no_run
+//! use chrono::NaiveDate;
+//! use oura_cli::OuraClient;
+//!
+//! let client = OuraClient::from_env()?;
+//! let day = NaiveDate::from_ymd_opt(2024, 2, 13).unwrap();
+//! for sleep in client.daily_sleep(day)? {
+//!     println!("{}: {:?}", sleep.day, sleep.score);
+//! }
+//! # Ok::<(), oura_cli::OuraError>(())
+//! ```
+
+pub mod analysis;
+pub mod async_client;
+pub mod cache;
+pub mod client;
+pub mod error;
+pub mod models;
+pub mod oauth;
+pub mod retry;
+pub mod sync;
+
+pub use async_client::AsyncOuraClient;
+pub use client::OuraClient;
+pub use error::{OuraError, Result};
@@ -1,11 +1,33 @@
-use anyhow::Result;
-use chrono::{Days, Local};
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use chrono::{Days, NaiveDate};
+use clap::builder::PossibleValuesParser;
 use clap::{Parser, Subcommand};
+use futures_util::future::join_all;
+use oura_cli::analysis::{self, group_by_day};
+use oura_cli::cache::Cache;
+use oura_cli::models::PersonalInfo;
+use oura_cli::oauth::{DEFAULT_REDIRECT_URI, DEFAULT_SCOPES, OAuthApp};
+use oura_cli::retry::RetryPolicy;
+use oura_cli::{AsyncOuraClient, OuraClient, OuraError, sync};
+use owo_colors::OwoColorize;
+use reqwest::StatusCode;
 
-mod client;
+mod auth;
+mod config;
+mod dates;
 mod display;
-#[allow(dead_code)]
-mod models;
+mod output;
+
+use auth::{Credential, OAuthLogin, TokenSource};
+use dates::{DateArgs, days, resolve_date, resolve_range, span_start};
+use output::Format;
 
 #[derive(Parser)]
 #[command(
@@ -16,157 +38,1168 @@ mod models;
 struct Cli {
     #[command(subcommand)]
     command: Option<Command>,
+
+    /// Output format: text, or json/csv/tsv for scripts [default: text, or the config's `format`]
+    #[arg(long, global = true, value_enum)]
+    format: Option<Format>,
+
+    /// Config file to read instead of ~/.config/oura/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// API token for this run (visible in process listings; prefer `oura auth login`)
+    #[arg(long, global = true)]
+    token: Option<String>,
+
+    /// Config profile to apply (default: $OURA_PROFILE, or the file's `default_profile`)
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Named account to use, from `[accounts.NAME]` in the config
+    #[arg(long, global = true, conflicts_with = "token")]
+    account: Option<String>,
+
+    /// Maximum pages to follow per request (default: all)
+    #[arg(long, global = true)]
+    max_pages: Option<usize>,
+
+    /// Serve everything from the local cache; never touch the network
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Skip the local cache and always fetch from the API
+    #[arg(long, global = true, conflicts_with = "offline")]
+    no_cache: bool,
+
+    /// Retries for rate-limited (429) or failed (5xx) requests
+    #[arg(long, global = true, default_value = "3")]
+    retries: u32,
+
+    /// Longest wait between retries, in seconds
+    #[arg(long, global = true, default_value = "30")]
+    max_retry_delay: u64,
+
+    /// Most API requests in flight at once, per account
+    #[arg(long, global = true, default_value = "4", value_parser = clap::value_parser!(u32).range(1..))]
+    concurrency: u32,
 }
 
 #[derive(Subcommand)]
 enum Command {
     /// Sleep + readiness + activity scores (default)
     Scores {
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
-        date: Option<String>,
+        #[command(flatten)]
+        dates: DateArgs,
+        /// Compare every account in the config, one row each
+        #[arg(long)]
+        all_accounts: bool,
     },
     /// Detailed sleep breakdown
     Sleep {
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
-        date: Option<String>,
+        #[command(flatten)]
+        dates: DateArgs,
     },
     /// Actionable sleep analysis
     Analyze {
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
+        /// Date: YYYY-MM-DD, "yesterday", "3 days ago", -3, "last monday", ...
+        #[arg(allow_negative_numbers = true)]
         date: Option<String>,
     },
     /// Sleep stage hypnogram (5-min intervals)
     Hypnogram {
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
+        /// Date: YYYY-MM-DD, "yesterday", "3 days ago", -3, "last monday", ...
+        #[arg(allow_negative_numbers = true)]
         date: Option<String>,
     },
     /// Readiness score and contributors
     Readiness {
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
-        date: Option<String>,
+        #[command(flatten)]
+        dates: DateArgs,
     },
     /// Activity summary (steps, calories, movement)
     Activity {
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
-        date: Option<String>,
+        #[command(flatten)]
+        dates: DateArgs,
     },
     /// Heart rate variability from sleep
     Hrv {
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
-        date: Option<String>,
+        #[command(flatten)]
+        dates: DateArgs,
     },
     /// Daily stress summary
     Stress {
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
+        #[command(flatten)]
+        dates: DateArgs,
+    },
+    /// Blood oxygen (SpO2) and breathing disturbance index
+    Spo2 {
+        #[command(flatten)]
+        dates: DateArgs,
+    },
+    /// Recommended bedtime window vs when you actually went to bed
+    Bedtime {
+        /// Date: YYYY-MM-DD, "yesterday", "3 days ago", -3, "last monday", ...
+        #[arg(allow_negative_numbers = true)]
         date: Option<String>,
+        /// Number of recent nights to compare
+        #[arg(short, long, default_value = "7", value_parser = clap::value_parser!(u32).range(1..))]
+        days: u32,
+    },
+    /// Cardiovascular age, resilience and VO2 max trend
+    Fitness {
+        /// Date: YYYY-MM-DD, "yesterday", "3 days ago", -3, "last monday", ...
+        #[arg(allow_negative_numbers = true)]
+        date: Option<String>,
+        /// Days of history for the VO2 max trend and latest readings
+        #[arg(short, long, default_value = "30", value_parser = clap::value_parser!(u32).range(1..))]
+        window: u32,
+    },
+    /// Tagged events (caffeine, alcohol, late meals, travel, ...)
+    Tags {
+        #[command(flatten)]
+        dates: DateArgs,
+    },
+    /// Workouts with activity, intensity, duration and calories, plus totals per activity
+    Workouts {
+        #[command(flatten)]
+        dates: DateArgs,
+    },
+    /// Meditation/breathing sessions; one session (or --detail) charts HR and HRV
+    Sessions {
+        #[command(flatten)]
+        dates: DateArgs,
+        /// Chart heart rate and HRV for every session, not just a lone one
+        #[arg(long)]
+        detail: bool,
+    },
+    /// Heart rate by source (awake, rest, sleep, workout) with a sparkline of the day
+    Heartrate {
+        #[command(flatten)]
+        dates: DateArgs,
     },
     /// Score trend over the last N days (default: 7)
     Trend {
         /// Number of days to show
-        #[arg(short, long, default_value = "7")]
+        #[arg(short, long, default_value = "7", value_parser = clap::value_parser!(u32).range(1..))]
         days: u32,
+        /// Last day of the trend (default: today)
+        #[arg(long, allow_negative_numbers = true)]
+        end: Option<String>,
+        /// Compare every account in the config side by side
+        #[arg(long)]
+        all_accounts: bool,
     },
     /// Raw JSON from any endpoint (for piping)
     Json {
         /// API endpoint (e.g. daily_sleep, sleep, daily_activity, daily_stress)
         endpoint: String,
-        /// Date: YYYY-MM-DD, "today", or "yesterday"
-        date: Option<String>,
+        #[command(flatten)]
+        dates: DateArgs,
     },
+    /// Mirror account history into the local cache, resuming where the last sync stopped
+    Sync {
+        /// Earliest day to archive (default: one year ago, or where the last sync began)
+        #[arg(long, allow_negative_numbers = true)]
+        since: Option<String>,
+        /// Only sync these endpoints (repeatable)
+        #[arg(
+            long = "endpoint",
+            value_name = "ENDPOINT",
+            value_parser = PossibleValuesParser::new(sync::ENDPOINTS.iter().map(|e| e.name)),
+        )]
+        endpoints: Vec<String>,
+        /// Don't ask to confirm the account before syncing
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Store, check or remove the API token
+    Auth {
+        #[command(subcommand)]
+        action: AuthCommand,
+    },
+    /// Show the account the token belongs to
+    Whoami,
+    /// Show the rings registered to the account
+    Ring,
+}
+
+#[derive(Subcommand)]
+enum AuthCommand {
+    /// Check a token against the API and store it in the OS keyring
+    Login {
+        /// Store it in a passphrase-encrypted file instead (uses OURA_PASSPHRASE if set)
+        #[arg(long)]
+        file: bool,
+        /// Sign in through the browser with an OAuth application instead of pasting a token
+        #[arg(long)]
+        oauth: bool,
+        /// OAuth client ID (default: `[oauth] client_id` in the config)
+        #[arg(long, requires = "oauth")]
+        client_id: Option<String>,
+        /// OAuth client secret (default: `[oauth] client_secret` in the config)
+        #[arg(long, requires = "oauth")]
+        client_secret: Option<String>,
+        /// Scope to request (repeatable; default: everything the CLI reads)
+        #[arg(long = "scope", requires = "oauth")]
+        scopes: Vec<String>,
+        /// Redirect URI registered for the application [default: `[oauth] redirect_uri`, or http://127.0.0.1:8765/callback]
+        #[arg(long, requires = "oauth")]
+        redirect_uri: Option<String>,
+        /// Print the authorization page instead of opening a browser
+        #[arg(long, requires = "oauth")]
+        no_browser: bool,
+    },
+    /// Show which token is in use and whether the API accepts it
+    Status,
+    /// Remove the stored token from the keyring and encrypted file
+    Logout,
+}
+
+/// How far before a trend's first day to look for a Rest Mode period that
+/// started earlier and is still running.
+const REST_MODE_LOOKBACK_DAYS: u64 = 60;
+
+/// Exit status for each failure class, so scripts can react without parsing stderr.
+fn exit_code(err: &OuraError) -> u8 {
+    match err {
+        OuraError::MissingToken => 3,
+        OuraError::Unauthorized { .. } => 4,
+        OuraError::RateLimited { .. } => 5,
+        OuraError::NotFound { .. } => 6,
+        OuraError::Server { .. } => 7,
+        OuraError::Api { .. } => 8,
+        OuraError::Network(_) => 9,
+        OuraError::Decode(_) => 10,
+        OuraError::InvalidDate(_) | OuraError::InvalidSpan(_) => 11,
+        OuraError::Cache(_) => 12,
+        OuraError::Io(_) => 13,
+        OuraError::Offline => 14,
+        OuraError::NoCache => 15,
+        OuraError::OAuth(_) => 16,
+        OuraError::Runtime(_) => 17,
+    }
+}
+
+fn hint(err: &OuraError) -> Option<String> {
+    match err {
+        OuraError::MissingToken => Some(
+            "run `oura auth login`, export OURA_TOKEN, or set token/token_env/token_file in the config; \
+             get a token at https://cloud.ouraring.com/personal-access-tokens"
+                .into(),
+        ),
+        OuraError::Unauthorized { .. } => Some(
+            "token is invalid or expired; regenerate it at https://cloud.ouraring.com/personal-access-tokens"
+                .into(),
+        ),
+        OuraError::RateLimited {
+            retry_after: Some(wait),
+            ..
+        } => Some(format!(
+            "rate limited; try again in {}s or raise --retries",
+            wait.as_secs()
+        )),
+        OuraError::RateLimited { .. } => {
+            Some("rate limited; wait a few minutes or raise --retries".into())
+        }
+        OuraError::NotFound { .. } => {
+            Some("check the endpoint name (e.g. daily_sleep, sleep, daily_activity)".into())
+        }
+        OuraError::Server { .. } => Some("Oura is having trouble; try again later".into()),
+        OuraError::Network(_) => {
+            Some("check your connection, or OURA_BASE_URL / base_url in the config".into())
+        }
+        OuraError::InvalidDate(_) => Some(
+            "use YYYY-MM-DD, today, yesterday, \"3 days ago\", -3, \"last monday\", \
+             2024-W07, 2024-02, \"this week\", \"last week\", \"this month\" or \"last month\""
+                .into(),
+        ),
+        OuraError::InvalidSpan(_) => {
+            Some("give a number of days like 14d, 2w or 7, small enough to stay in range".into())
+        }
+        OuraError::Cache(_) | OuraError::Io(_) => Some(format!(
+            "check permissions and free space in {}, or pass --no-cache; \
+             the directory also holds the `oura sync` archive",
+            Cache::default_path()
+                .as_deref()
+                .and_then(std::path::Path::parent)
+                .map_or("<data dir>".into(), |p| p.display().to_string())
+        )),
+        OuraError::Offline => Some("drop --no-cache to read cached days offline".into()),
+        OuraError::NoCache => Some("drop --no-cache; sync stores into the local cache".into()),
+        OuraError::OAuth(_) => Some("run `oura auth login --oauth` again".into()),
+        OuraError::Api { .. } | OuraError::Decode(_) | OuraError::Runtime(_) => None,
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            match err.downcast_ref::<OuraError>() {
+                Some(oura_err) => {
+                    if let Some(hint) = hint(oura_err) {
+                        eprintln!("hint: {hint}");
+                    }
+                    ExitCode::from(exit_code(oura_err))
+                }
+                None => ExitCode::FAILURE,
+            }
+        }
+    }
+}
+
+/// Renders one block per day, with a date header when the range spans several.
+fn each_day(start: NaiveDate, end: NaiveDate, mut render: impl FnMut(&str)) {
+    let days = days(start, end);
+    for (idx, date) in days.iter().enumerate() {
+        if days.len() > 1 {
+            if idx > 0 {
+                println!();
+            }
+            println!("  {}", date.format("%a %b %d").to_string().bold());
+        }
+        render(&date.to_string());
+    }
+}
+
+/// Names the account a sync is about to write to the cache. Interactive runs
+/// must confirm it unless `yes` is set; returns false if the user declines.
+fn confirm_account(client: &OuraClient, yes: bool) -> Result<bool> {
+    match client.personal_info() {
+        Ok(info) => println!(
+            "Syncing account {}",
+            info.email
+                .or(info.id)
+                .as_deref()
+                .unwrap_or("(unknown)")
+                .bold()
+        ),
+        Err(err) => eprintln!(
+            "{}",
+            format!("warning: could not look up the account: {err}").yellow()
+        ),
+    }
+
+    if yes || !std::io::stdin().is_terminal() {
+        return Ok(true);
+    }
+    eprint!("Continue? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// One machine-readable row per day in `start..=end`.
+fn per_day<T>(start: NaiveDate, end: NaiveDate, row: impl Fn(&str) -> T) -> Vec<T> {
+    days(start, end)
+        .iter()
+        .map(|date| row(&date.to_string()))
+        .collect()
+}
+
+fn first<'a, T>(groups: &'a HashMap<String, Vec<T>>, day: &str) -> Option<&'a T> {
+    groups.get(day)?.first()
+}
+
+fn all<'a, T>(groups: &'a HashMap<String, Vec<T>>, day: &str) -> &'a [T] {
+    groups.get(day).map_or(&[], Vec::as_slice)
+}
+
+/// A client for `credential` with the retry, paging, offline and host
+/// settings from the command line and config. The cache is left to the caller.
+fn new_client(cli: &Cli, config: &config::Config, credential: &Credential) -> Result<OuraClient> {
+    let retry = RetryPolicy {
+        max_retries: cli.retries,
+        max_delay: Duration::from_secs(cli.max_retry_delay),
+        ..Default::default()
+    };
+    let client = match credential {
+        Credential::Token(token) => AsyncOuraClient::new(token),
+        Credential::OAuth(login) => {
+            AsyncOuraClient::new("").with_oauth(login.app(), login.token.clone())
+        }
+    }
+    .with_max_pages(cli.max_pages)
+    .on_truncated(|endpoint, pages| {
+        eprintln!("warning: {endpoint} has more data; stopped after {pages} page(s) (--max-pages)");
+    })
+    .with_max_concurrency(cli.concurrency as usize)
+    .with_retry(retry)
+    .with_offline(cli.offline)
+    .on_uncached(|endpoint, days| {
+        let (first, last) = (days[0], days[days.len() - 1]);
+        let span = if first == last {
+            first.to_string()
+        } else {
+            format!("{} days, {first} → {last}", days.len())
+        };
+        eprintln!(
+            "warning: {endpoint} isn't cached for {span}; sync or drop --offline to fetch it"
+        );
+    });
+    let client = match config.base_url() {
+        Some(url) => client.with_base_url(url),
+        None => client,
+    };
+    Ok(OuraClient::from_async(client)?)
+}
+
+/// A client for the highest-precedence credential. Refreshed OAuth tokens
+/// are written back to where they were stored.
+fn connect(
+    cli: &Cli,
+    config: &config::Config,
+    store: &Arc<auth::Store>,
+    account: Option<&str>,
+) -> Result<(OuraClient, Credential, TokenSource)> {
+    let (credential, source) = match (store.resolve(cli.token.as_deref())?, account) {
+        (Some(found), _) => found,
+        (None, Some(name)) => {
+            return Err(OuraError::MissingToken)
+                .with_context(|| format!("no token stored or configured for account {name}"));
+        }
+        (None, None) => return Err(OuraError::MissingToken.into()),
+    };
+    let mut client = new_client(cli, config, &credential)?;
+    if let Credential::OAuth(login) = &credential {
+        let (store, source, login) = (Arc::clone(store), source.clone(), login.clone());
+        client = client.on_token_refresh(move |token| {
+            let login = OAuthLogin {
+                token: token.clone(),
+                ..login.clone()
+            };
+            if let Err(err) = store.update(&source, &login) {
+                eprintln!(
+                    "{}",
+                    format!("warning: could not save the refreshed token: {err:#}").yellow()
+                );
+            }
+        });
+    }
+    Ok((client, credential, source))
+}
+
+/// A connected client for `account` (the default one when `None`), reading
+/// through that account's cache unless `--no-cache`.
+fn open_client(cli: &Cli, config: &config::Config, account: Option<&str>) -> Result<OuraClient> {
+    let store = Arc::new(auth::Store::for_config(config, account)?);
+    let (mut client, credential, source) = connect(cli, config, &store, account)?;
+    if !cli.no_cache {
+        let owner = credential.fingerprint(&source);
+        match config
+            .cache_path(account, &owner)
+            .map(|path| Cache::open(&path))
+        {
+            Some(Ok(cache)) => client = client.with_cache(cache),
+            Some(Err(err)) => eprintln!("warning: cache unavailable: {err}"),
+            None => {}
+        }
+    }
+    Ok(client)
+}
+
+/// Runs `fetch` for every `[accounts.NAME]` concurrently, returning results
+/// in name order. An account that fails is reported and left out, unless
+/// they all fail.
+fn each_account<T>(
+    cli: &Cli,
+    config: &config::Config,
+    fetch: impl AsyncFn(&AsyncOuraClient) -> Result<T>,
+) -> Result<Vec<(String, T)>> {
+    if cli.account.is_some() {
+        bail!("--all-accounts already covers every account; drop --account");
+    }
+    if config.accounts.is_empty() {
+        bail!("no accounts to compare; add [accounts.NAME] tables to the config");
+    }
+    let skip = |name: &str, err: &anyhow::Error| {
+        eprintln!(
+            "{}",
+            format!("warning: skipping account {name}: {err}").yellow()
+        );
+    };
+
+    // Tokens are resolved one account at a time, as they may prompt.
+    let mut clients = Vec::new();
+    let mut last_err = None;
+    for name in config.accounts.keys() {
+        match open_client(cli, config, Some(name)) {
+            Ok(client) => clients.push((name, client)),
+            Err(err) => {
+                skip(name, &err);
+                last_err = Some(err);
+            }
+        }
+    }
+    let Some((_, driver)) = clients.first() else {
+        return Err(last_err.expect("accounts is not empty"));
+    };
+
+    // One runtime drives every account's requests; each client still caps
+    // its own concurrency.
+    let fetched = driver.block_on(join_all(
+        clients.iter().map(|(_, client)| fetch(client.as_async())),
+    ));
+    let mut results = Vec::new();
+    for ((name, _), result) in clients.iter().zip(fetched) {
+        match result {
+            Ok(value) => results.push((name.to_string(), value)),
+            Err(err) => {
+                skip(name, &err);
+                last_err = Some(err);
+            }
+        }
+    }
+    match last_err {
+        Some(err) if results.is_empty() => Err(err),
+        _ => Ok(results),
+    }
+}
+
+/// Scores, SpO2 and Rest Mode for each day in `start..=end`.
+async fn fetch_trend(
+    api: &AsyncOuraClient,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<output::Trend> {
+    // Periods are keyed by their start day; reach back for one already running.
+    let rest_from = start
+        .checked_sub_days(Days::new(REST_MODE_LOOKBACK_DAYS))
+        .unwrap_or(start);
+    let (sleep, readiness, activity, spo2, rest_mode) = tokio::try_join!(
+        api.daily_sleep_range(start, end),
+        api.daily_readiness_range(start, end),
+        api.daily_activity_range(start, end),
+        api.daily_spo2_range(start, end),
+        api.rest_mode_periods_range(rest_from, end),
+    )?;
+
+    Ok(output::Trend::new(
+        &days(start, end),
+        &sleep,
+        &readiness,
+        &activity,
+        &spo2,
+        &rest_mode,
+    ))
+}
+
+/// `scores --all-accounts`: per day, one row per account.
+fn compare_scores(
+    cli: &Cli,
+    config: &config::Config,
+    dates: &DateArgs,
+    format: Format,
+) -> Result<()> {
+    let (start, end) = dates.resolve()?;
+    let accounts = each_account(cli, config, async |api| {
+        let (sleep, readiness, activity) = tokio::try_join!(
+            api.daily_sleep_range(start, end),
+            api.daily_readiness_range(start, end),
+            api.daily_activity_range(start, end),
+        )?;
+        let (sleep, readiness, activity) = (
+            group_by_day(sleep),
+            group_by_day(readiness),
+            group_by_day(activity),
+        );
+        Ok(per_day(start, end, |day| {
+            output::ScoresRow::new(
+                day,
+                first(&sleep, day),
+                first(&readiness, day),
+                first(&activity, day),
+            )
+        }))
+    })?;
+
+    let rows_for = |idx: usize| -> Vec<output::AccountRow<&output::ScoresRow>> {
+        accounts
+            .iter()
+            .map(|(name, rows)| output::AccountRow {
+                account: name,
+                row: &rows[idx],
+            })
+            .collect()
+    };
+    if format.is_text() {
+        let mut idx = 0;
+        each_day(start, end, |_| {
+            display::display_account_scores(&rows_for(idx), &config.thresholds);
+            idx += 1;
+        });
+    } else {
+        let rows: Vec<_> = (0..days(start, end).len()).flat_map(rows_for).collect();
+        output::emit(format, &rows)?;
+    }
+    Ok(())
 }
 
-fn resolve_date(input: Option<&str>) -> String {
-    let today = Local::now().date_naive();
-    match input.map(|s| s.to_lowercase()).as_deref() {
-        None | Some("today") => today.format("%Y-%m-%d").to_string(),
-        Some("yesterday") => today
-            .pred_opt()
-            .expect("date underflow")
-            .format("%Y-%m-%d")
-            .to_string(),
-        Some(date) => date.to_string(),
+/// `trend --all-accounts`: each account's trend in its own columns.
+fn compare_trend(
+    cli: &Cli,
+    config: &config::Config,
+    (start, end): (NaiveDate, NaiveDate),
+    format: Format,
+) -> Result<()> {
+    let accounts = each_account(cli, config, async |api| fetch_trend(api, start, end).await)?;
+    let trends: Vec<_> = accounts
+        .iter()
+        .map(|(name, trend)| output::AccountRow {
+            account: name,
+            row: trend,
+        })
+        .collect();
+    match format {
+        Format::Text => display::display_trend_comparison(&trends, &config.thresholds),
+        Format::Json => output::emit(format, &trends)?,
+        Format::Csv | Format::Tsv => {
+            let rows: Vec<_> = trends
+                .iter()
+                .flat_map(|t| {
+                    t.row.table().into_iter().map(|row| output::AccountRow {
+                        account: t.account,
+                        row,
+                    })
+                })
+                .collect();
+            output::emit(format, &rows)?
+        }
     }
+    Ok(())
 }
 
-fn main() -> Result<()> {
+/// First and last day of a trend of `n` days ending at `end` (default: today).
+fn trend_range(n: u32, end: Option<&str>) -> Result<(NaiveDate, NaiveDate)> {
+    let (_, end) = resolve_range(end)?;
+    Ok((span_start(end, n)?, end))
+}
+
+fn run_auth(
+    action: &AuthCommand,
+    cli: &Cli,
+    config: &config::Config,
+    store: &Arc<auth::Store>,
+) -> Result<()> {
+    if cli.offline && !matches!(action, AuthCommand::Logout) {
+        bail!("auth checks the token with the API; drop --offline");
+    }
+    let account = |info: &PersonalInfo| {
+        info.email
+            .clone()
+            .or(info.id.clone())
+            .unwrap_or_else(|| "(unknown)".into())
+    };
+
+    match action {
+        AuthCommand::Login {
+            file, oauth: false, ..
+        } => {
+            let token = auth::read_token()?;
+            let credential = Credential::Token(token.clone());
+            let info = new_client(cli, config, &credential)?.personal_info()?;
+            let source = store.save(&token, *file)?;
+            println!("Logged in as {}", account(&info).bold());
+            println!("Token saved to the {source}");
+        }
+        AuthCommand::Login {
+            file,
+            oauth: true,
+            client_id,
+            client_secret,
+            scopes,
+            redirect_uri,
+            no_browser,
+        } => {
+            let settings = &config.oauth;
+            let client_id = client_id
+                .clone()
+                .or(settings.client_id.clone())
+                .context("pass --client-id or set client_id under [oauth] in the config")?;
+            let mut app = OAuthApp::new(
+                client_id,
+                client_secret.clone().or(settings.client_secret.clone()),
+            );
+            if let Some(url) = &settings.authorize_url {
+                app.authorize_url.clone_from(url);
+            }
+            if let Some(url) = &settings.token_url {
+                app.token_url.clone_from(url);
+            }
+            let scopes: Vec<&str> = if !scopes.is_empty() {
+                scopes.iter().map(String::as_str).collect()
+            } else if let Some(configured) = &settings.scopes {
+                configured.iter().map(String::as_str).collect()
+            } else {
+                DEFAULT_SCOPES.to_vec()
+            };
+
+            let redirect_uri = redirect_uri
+                .as_deref()
+                .or(settings.redirect_uri.as_deref())
+                .unwrap_or(DEFAULT_REDIRECT_URI);
+            let token = auth::oauth_login(&app, &scopes, redirect_uri, !no_browser)?;
+            let login = OAuthLogin::new(&app, token);
+            let credential = Credential::OAuth(login.clone());
+            let info = new_client(cli, config, &credential)?.personal_info()?;
+            let source = store.save(&serde_json::to_string(&login)?, *file)?;
+            println!("Logged in as {}", account(&info).bold());
+            println!("OAuth tokens saved to the {source}");
+        }
+        AuthCommand::Status => {
+            let (client, credential, source) = connect(cli, config, store, cli.account.as_deref())?;
+            if let Some(profile) = &config.profile {
+                println!("  Profile: {profile}");
+            }
+            if let Some(name) = &cli.account {
+                println!("  Name:    {name}");
+            }
+            println!("  Source:  {source}");
+            if let Credential::OAuth(login) = &credential {
+                println!(
+                    "  Type:    OAuth ({})",
+                    login.token.scope.as_deref().unwrap_or("scopes unknown")
+                );
+            }
+            let info = client.personal_info()?;
+            println!("  Account: {}", account(&info));
+            println!("  Status:  {}", "valid".green());
+        }
+        AuthCommand::Logout => {
+            let removed = store.clear()?;
+            if removed.is_empty() {
+                println!("No stored token to remove");
+            }
+            for source in removed {
+                println!("Removed the token from the {source}");
+            }
+            if cli.account.is_none() && std::env::var_os("OURA_TOKEN").is_some() {
+                eprintln!(
+                    "{}",
+                    "note: OURA_TOKEN is still set in this environment".yellow()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run() -> Result<()> {
     let cli = Cli::parse();
-    let client = client::OuraClient::new()?;
-    let cmd = cli.command.unwrap_or(Command::Scores { date: None });
+    let config = config::Config::load(cli.config.as_deref(), cli.profile.as_deref())?;
+    let format = cli.format.or(config.format).unwrap_or_default();
+    match &cli.command {
+        Some(Command::Auth { action }) => {
+            let store = Arc::new(auth::Store::for_config(&config, cli.account.as_deref())?);
+            return run_auth(action, &cli, &config, &store);
+        }
+        Some(Command::Scores {
+            dates,
+            all_accounts: true,
+        }) => return compare_scores(&cli, &config, dates, format),
+        Some(Command::Trend {
+            days: n,
+            end,
+            all_accounts: true,
+        }) => return compare_trend(&cli, &config, trend_range(*n, end.as_deref())?, format),
+        _ => {}
+    }
+
+    let client = open_client(&cli, &config, cli.account.as_deref())?;
+    // Commands that read several endpoints request them concurrently through it.
+    let api = client.as_async();
+    let cmd = cli.command.unwrap_or(Command::Scores {
+        dates: DateArgs::default(),
+        all_accounts: false,
+    });
 
     match cmd {
-        Command::Scores { date } => {
-            let d = resolve_date(date.as_deref());
-            let sleep = client.daily_sleep(&d)?;
-            let readiness = client.daily_readiness(&d)?;
-            let activity = client.daily_activity(&d)?;
-            display::display_scores(sleep.first(), readiness.first(), activity.first());
-        }
-        Command::Sleep { date } => {
-            let d = resolve_date(date.as_deref());
-            let sleep = client.sleep(&d)?;
-            let daily = client.daily_sleep(&d)?;
-            display::display_sleep(daily.first(), &sleep);
+        Command::Scores { dates, .. } => {
+            let (start, end) = dates.resolve()?;
+            let (sleep, readiness, activity) = client.block_on(async {
+                tokio::try_join!(
+                    api.daily_sleep_range(start, end),
+                    api.daily_readiness_range(start, end),
+                    api.daily_activity_range(start, end),
+                )
+            })?;
+            let sleep = group_by_day(sleep);
+            let readiness = group_by_day(readiness);
+            let activity = group_by_day(activity);
+            if format.is_text() {
+                each_day(start, end, |day| {
+                    display::display_scores(
+                        first(&sleep, day),
+                        first(&readiness, day),
+                        first(&activity, day),
+                        &config.thresholds,
+                    )
+                });
+            } else {
+                let rows = per_day(start, end, |day| {
+                    output::ScoresRow::new(
+                        day,
+                        first(&sleep, day),
+                        first(&readiness, day),
+                        first(&activity, day),
+                    )
+                });
+                output::emit(format, &rows)?;
+            }
+        }
+        Command::Sleep { dates } => {
+            let (start, end) = dates.resolve()?;
+            let (sleep, daily) = client.block_on(async {
+                tokio::try_join!(
+                    api.sleep_range(start, end),
+                    api.daily_sleep_range(start, end)
+                )
+            })?;
+            let (sleep, daily) = (group_by_day(sleep), group_by_day(daily));
+            if format.is_text() {
+                each_day(start, end, |day| {
+                    display::display_sleep(first(&daily, day), all(&sleep, day), &config.thresholds)
+                });
+            } else {
+                let rows = per_day(start, end, |day| {
+                    output::SleepRow::new(day, first(&daily, day), all(&sleep, day))
+                });
+                output::emit(format, &rows)?;
+            }
         }
         Command::Analyze { date } => {
-            let d = resolve_date(date.as_deref());
-            let sleep = client.sleep(&d)?;
-            let daily_sleep = client.daily_sleep(&d)?;
-            let daily_readiness = client.daily_readiness(&d)?;
-            display::display_analyze(daily_sleep.first(), daily_readiness.first(), &sleep);
+            let d = resolve_date(date.as_deref())?;
+            // Tags from the evening before count against this night.
+            let evening = d.pred_opt().unwrap_or(d);
+            let (sleep, daily_sleep, daily_readiness, daily_spo2, tags, enhanced_tags) = client
+                .block_on(async {
+                    tokio::try_join!(
+                        api.sleep(d),
+                        api.daily_sleep(d),
+                        api.daily_readiness(d),
+                        api.daily_spo2(d),
+                        api.tags_range(evening, d),
+                        api.enhanced_tags_range(evening, d),
+                    )
+                })?;
+            let tags = analysis::tag_events(&tags, &enhanced_tags);
+            let analysis = analysis::analyze(
+                daily_sleep.first(),
+                daily_readiness.first(),
+                daily_spo2.first(),
+                &sleep,
+                &tags,
+                &config.thresholds,
+            );
+            match format {
+                Format::Text => display::display_analyze(&analysis, &config.thresholds),
+                Format::Json => output::emit(format, &analysis)?,
+                Format::Csv | Format::Tsv => {
+                    output::emit_rows(format, &output::flag_rows(&analysis))?
+                }
+            }
         }
         Command::Hypnogram { date } => {
-            let d = resolve_date(date.as_deref());
-            let sleep = client.sleep(&d)?;
-            let daily = client.daily_sleep(&d)?;
-            display::display_hypnogram(daily.first(), &sleep);
-        }
-        Command::Readiness { date } => {
-            let d = resolve_date(date.as_deref());
-            let data = client.daily_readiness(&d)?;
-            display::display_readiness(data.first());
-        }
-        Command::Activity { date } => {
-            let d = resolve_date(date.as_deref());
-            let data = client.daily_activity(&d)?;
-            display::display_activity(data.first());
-        }
-        Command::Hrv { date } => {
-            let d = resolve_date(date.as_deref());
-            let sleep = client.sleep(&d)?;
-            let daily = client.daily_sleep(&d)?;
-            display::display_hrv(daily.first(), &sleep);
-        }
-        Command::Stress { date } => {
-            let d = resolve_date(date.as_deref());
-            let data = client.daily_stress(&d)?;
-            display::display_stress(data.first());
-        }
-        Command::Trend { days } => {
-            let today = Local::now().date_naive();
-            let start = today
-                .checked_sub_days(Days::new((days - 1) as u64))
-                .expect("date underflow");
-            let start_str = start.format("%Y-%m-%d").to_string();
-            let end_str = today.format("%Y-%m-%d").to_string();
-
-            let sleep = client.daily_sleep_range(&start_str, &end_str)?;
-            let readiness = client.daily_readiness_range(&start_str, &end_str)?;
-            let activity = client.daily_activity_range(&start_str, &end_str)?;
-
-            // Build list of all dates in range
-            let mut date_list = Vec::new();
-            let mut d = start;
-            while d <= today {
-                date_list.push(d.format("%Y-%m-%d").to_string());
-                d = d.succ_opt().expect("date overflow");
-            }
-
-            display::display_trend(&date_list, &sleep, &readiness, &activity);
-        }
-        Command::Json { endpoint, date } => {
-            let d = resolve_date(date.as_deref());
-            let json = client.raw(&endpoint, &d)?;
-            println!("{}", serde_json::to_string_pretty(&json)?);
+            let d = resolve_date(date.as_deref())?;
+            let (sleep, daily) =
+                client.block_on(async { tokio::try_join!(api.sleep(d), api.daily_sleep(d)) })?;
+            if format.is_text() {
+                display::display_hypnogram(daily.first(), &sleep, &config.thresholds);
+            } else {
+                output::emit_rows(format, &output::phase_rows(&sleep))?;
+            }
+        }
+        Command::Readiness { dates } => {
+            let (start, end) = dates.resolve()?;
+            let data = group_by_day(client.daily_readiness_range(start, end)?);
+            if format.is_text() {
+                each_day(start, end, |day| {
+                    display::display_readiness(first(&data, day), &config.thresholds)
+                });
+            } else {
+                let rows = per_day(start, end, |day| {
+                    output::ReadinessRow::new(day, first(&data, day))
+                });
+                output::emit(format, &rows)?;
+            }
+        }
+        Command::Activity { dates } => {
+            let (start, end) = dates.resolve()?;
+            let data = group_by_day(client.daily_activity_range(start, end)?);
+            if format.is_text() {
+                each_day(start, end, |day| {
+                    display::display_activity(first(&data, day), &config.thresholds)
+                });
+            } else {
+                let rows = per_day(start, end, |day| {
+                    output::ActivityRow::new(day, first(&data, day))
+                });
+                output::emit(format, &rows)?;
+            }
+        }
+        Command::Hrv { dates } => {
+            let (start, end) = dates.resolve()?;
+            let (sleep, daily) = client.block_on(async {
+                tokio::try_join!(
+                    api.sleep_range(start, end),
+                    api.daily_sleep_range(start, end)
+                )
+            })?;
+            let (sleep, daily) = (group_by_day(sleep), group_by_day(daily));
+            if format.is_text() {
+                each_day(start, end, |day| {
+                    display::display_hrv(first(&daily, day), all(&sleep, day), &config.thresholds)
+                });
+            } else {
+                let rows = per_day(start, end, |day| output::HrvRow::new(day, all(&sleep, day)));
+                output::emit(format, &rows)?;
+            }
+        }
+        Command::Stress { dates } => {
+            let (start, end) = dates.resolve()?;
+            let data = group_by_day(client.daily_stress_range(start, end)?);
+            if format.is_text() {
+                each_day(start, end, |day| display::display_stress(first(&data, day)));
+            } else {
+                let rows = per_day(start, end, |day| {
+                    output::StressRow::new(day, first(&data, day))
+                });
+                output::emit(format, &rows)?;
+            }
+        }
+        Command::Spo2 { dates } => {
+            let (start, end) = dates.resolve()?;
+            let data = group_by_day(client.daily_spo2_range(start, end)?);
+            if format.is_text() {
+                each_day(start, end, |day| {
+                    display::display_spo2(first(&data, day), &config.thresholds)
+                });
+            } else {
+                let rows = per_day(start, end, |day| {
+                    output::Spo2Row::new(day, first(&data, day))
+                });
+                output::emit(format, &rows)?;
+            }
+        }
+        Command::Bedtime { date, days: n } => {
+            let end = resolve_date(date.as_deref())?;
+            let start = span_start(end, n)?;
+            let (sleep_times, sleep) = client.block_on(async {
+                tokio::try_join!(
+                    api.sleep_time_range(start, end),
+                    api.sleep_range(start, end)
+                )
+            })?;
+            let bedtime = analysis::bedtime(&end.to_string(), &sleep_times, &sleep);
+            match format {
+                Format::Text => display::display_bedtime(&bedtime),
+                Format::Json => output::emit(format, &bedtime)?,
+                Format::Csv | Format::Tsv => output::emit_rows(format, &bedtime.nights)?,
+            }
+        }
+        Command::Fitness { date, window } => {
+            let end = resolve_date(date.as_deref())?;
+            let start = span_start(end, window)?;
+            let (cardiovascular_age, resilience, vo2_max) = client.block_on(async {
+                tokio::try_join!(
+                    api.daily_cardiovascular_age_range(start, end),
+                    api.daily_resilience_range(start, end),
+                    api.vo2_max_range(start, end),
+                )
+            })?;
+
+            match format {
+                Format::Text | Format::Json => {
+                    let fitness = analysis::fitness(
+                        &end.to_string(),
+                        &cardiovascular_age,
+                        &resilience,
+                        &vo2_max,
+                    );
+                    if format.is_text() {
+                        display::display_fitness(&fitness, window.into(), &config.thresholds);
+                    } else {
+                        output::emit(format, &fitness)?;
+                    }
+                }
+                Format::Csv | Format::Tsv => {
+                    let cardiovascular_age = group_by_day(cardiovascular_age);
+                    let resilience = group_by_day(resilience);
+                    let vo2_max = group_by_day(vo2_max);
+                    let rows = per_day(start, end, |day| {
+                        output::FitnessRow::new(
+                            day,
+                            first(&cardiovascular_age, day),
+                            first(&resilience, day),
+                            first(&vo2_max, day),
+                        )
+                    });
+                    output::emit(format, &rows)?;
+                }
+            }
+        }
+        Command::Tags { dates } => {
+            let (start, end) = dates.resolve()?;
+            let (tags, enhanced_tags) = client.block_on(async {
+                tokio::try_join!(
+                    api.tags_range(start, end),
+                    api.enhanced_tags_range(start, end)
+                )
+            })?;
+            let tags = analysis::tag_events(&tags, &enhanced_tags);
+            if format.is_text() {
+                display::display_tags(&tags);
+            } else {
+                output::emit_rows(format, &tags)?;
+            }
+        }
+        Command::Workouts { dates } => {
+            let (start, end) = dates.resolve()?;
+            let workouts = client.workouts_range(start, end)?;
+            match format {
+                Format::Text => display::display_workouts(&workouts),
+                Format::Json => output::emit(format, &output::Workouts::new(&workouts))?,
+                Format::Csv | Format::Tsv => {
+                    output::emit_rows(format, &output::workout_rows(&workouts))?
+                }
+            }
+        }
+        Command::Sessions { dates, detail } => {
+            let (start, end) = dates.resolve()?;
+            let sessions = client.sessions_range(start, end)?;
+            if format.is_text() {
+                display::display_sessions(&sessions, detail);
+            } else {
+                output::emit_rows(format, &output::session_rows(&sessions))?;
+            }
+        }
+        Command::Heartrate { dates } => {
+            let (start, end) = dates.resolve()?;
+            let samples = client.heartrate_range(start, end)?;
+            match format {
+                Format::Text => {
+                    let data = group_by_day(samples);
+                    each_day(start, end, |day| {
+                        display::display_heartrate(all(&data, day))
+                    });
+                }
+                Format::Json => {
+                    let data = group_by_day(samples);
+                    let days = per_day(start, end, |day| {
+                        output::HeartRateDay::new(day, all(&data, day))
+                    });
+                    output::emit(format, &days)?;
+                }
+                Format::Csv | Format::Tsv => {
+                    output::emit_rows(format, &output::sample_rows(&samples))?
+                }
+            }
+        }
+        Command::Trend { days: n, end, .. } => {
+            let (start, end) = trend_range(n, end.as_deref())?;
+            let trend = client.block_on(fetch_trend(api, start, end))?;
+            match format {
+                Format::Text => display::display_trend(&trend, &config.thresholds),
+                Format::Json => output::emit(format, &trend)?,
+                Format::Csv | Format::Tsv => output::emit(format, &trend.table())?,
+            }
+        }
+        Command::Json { endpoint, dates } => {
+            let (start, end) = dates.resolve()?;
+            let json = if start == end {
+                client.raw(&endpoint, start)?
+            } else {
+                client.raw_range(&endpoint, start, end)?
+            };
+            match format {
+                Format::Text | Format::Json => {
+                    println!("{}", serde_json::to_string_pretty(&json)?)
+                }
+                // One row per record
+                Format::Csv | Format::Tsv => output::emit(format, &json["data"])?,
+            }
+        }
+        Command::Auth { .. } => unreachable!("auth runs before a client is built"),
+        Command::Whoami => {
+            if cli.offline {
+                bail!("whoami always asks the API; drop --offline");
+            }
+            let info = client.personal_info()?;
+            if format.is_text() {
+                display::display_whoami(&info);
+            } else {
+                output::emit(format, &info)?;
+            }
+        }
+        Command::Ring => {
+            if cli.offline {
+                bail!("ring always asks the API; drop --offline");
+            }
+            let rings = client.ring_configurations()?;
+            if format.is_text() {
+                display::display_rings(&rings);
+            } else {
+                output::emit_rows(format, &rings)?;
+            }
+        }
+        Command::Sync {
+            since,
+            endpoints,
+            yes,
+        } => {
+            let since = match since {
+                Some(s) => Some(resolve_range(Some(&s))?.0),
+                None => None,
+            };
+            if cli.no_cache {
+                return Err(OuraError::NoCache.into());
+            }
+            if cli.offline {
+                bail!("sync needs the network; drop --offline");
+            }
+            if !confirm_account(&client, yes)? {
+                eprintln!("Sync cancelled.");
+                return Ok(());
+            }
+
+            let selected: Vec<_> = sync::ENDPOINTS
+                .iter()
+                .filter(|e| endpoints.is_empty() || endpoints.iter().any(|n| n == e.name))
+                .collect();
+            // Endpoints sync side by side; each one's chunks stay in order.
+            let results = client.block_on(join_all(selected.iter().map(|endpoint| {
+                api.sync(endpoint, since, |chunk| {
+                    println!(
+                        "  {:<26}{} → {}  {:>6} records",
+                        endpoint.name, chunk.start, chunk.end, chunk.records
+                    );
+                })
+            })));
+
+            let mut failed = 0;
+            for (endpoint, result) in selected.iter().zip(results) {
+                match result {
+                    Ok(_) => {}
+                    // Endpoints the account or token scope doesn't cover; keep going.
+                    Err(
+                        err @ (OuraError::NotFound { .. }
+                        | OuraError::Api { .. }
+                        | OuraError::Unauthorized {
+                            status: StatusCode::FORBIDDEN,
+                            ..
+                        }),
+                    ) => {
+                        failed += 1;
+                        eprintln!("  {:<26}{}", endpoint.name, format!("skipped: {err}").red());
+                    }
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            if failed > 0 {
+                eprintln!("  {}", format!("{failed} endpoint(s) skipped").yellow());
+            }
         }
     }
 
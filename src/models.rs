@@ -1,26 +1,240 @@
-use serde::Deserialize;
+//! Records returned by the Oura API v2. Fields mirror the API's JSON names;
+//! anything the API may omit is an `Option`.
 
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// One page of a `usercollection` endpoint.
 #[derive(Debug, Deserialize)]
 pub struct ApiResponse<T> {
     pub data: Vec<T>,
+    /// Present when more results are available; pass back as `next_token`.
+    pub next_token: Option<String>,
+}
+
+/// Records that belong to one calendar day (`YYYY-MM-DD`).
+pub trait Dated {
+    fn day(&self) -> Cow<'_, str>;
+}
+
+macro_rules! impl_dated {
+    ($($ty:ty),*) => {
+        $(impl Dated for $ty {
+            fn day(&self) -> Cow<'_, str> {
+                Cow::Borrowed(&self.day)
+            }
+        })*
+    };
+}
+
+impl_dated!(
+    DailySleep,
+    DailyReadiness,
+    DailyActivity,
+    Sleep,
+    DailyStress,
+    Workout,
+    Session,
+    Tag,
+    DailySpo2,
+    DailyCardiovascularAge,
+    DailyResilience,
+    Vo2Max,
+    SleepTime
+);
+
+impl Dated for RestModePeriod {
+    fn day(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.start_day)
+    }
+}
+
+impl Dated for EnhancedTag {
+    fn day(&self) -> Cow<'_, str> {
+        Cow::Borrowed(&self.start_day)
+    }
+}
+
+/// Evenly spaced samples: `items[i]` was taken `i * interval` seconds after
+/// `timestamp`. Gaps (e.g. no reading while moving) are `None`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeSeries {
+    pub interval: f64,
+    pub items: Vec<Option<f64>>,
+    pub timestamp: String,
 }
 
+impl TimeSeries {
+    /// Each item with the time it was taken. Empty if `timestamp` doesn't parse.
+    pub fn samples(&self) -> impl Iterator<Item = (DateTime<FixedOffset>, Option<f64>)> + '_ {
+        let start = DateTime::parse_from_rfc3339(&self.timestamp).ok();
+        let step = Duration::milliseconds((self.interval * 1000.0).round() as i64);
+        start.into_iter().flat_map(move |start| {
+            (0..)
+                .map(move |i| start + step * i)
+                .zip(self.items.iter().copied())
+        })
+    }
+}
+
+/// A value encoded as one digit of a sequence string such as
+/// `sleep_phase_5_min`.
+pub trait Code: Copy {
+    fn from_code(code: char) -> Self;
+}
+
+/// A string of one-digit codes, one per fixed interval, decoded into `T`.
+/// The API sends these without a start time or interval; the record they
+/// belong to supplies both (see [`Sleep::phases`]).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "String", bound = "T: Code")]
+pub struct Sequence<T>(pub Vec<T>);
+
+impl<T: Code> From<String> for Sequence<T> {
+    fn from(codes: String) -> Self {
+        Self(codes.chars().map(T::from_code).collect())
+    }
+}
+
+impl<T: Code> Sequence<T> {
+    /// Each item with the start of its interval, the first at `start`.
+    pub fn timed(
+        &self,
+        start: DateTime<FixedOffset>,
+        interval: Duration,
+    ) -> impl Iterator<Item = (DateTime<FixedOffset>, T)> + '_ {
+        (0..)
+            .map(move |i| start + interval * i)
+            .zip(self.0.iter().copied())
+    }
+}
+
+/// One interval of a hypnogram.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SleepPhase {
+    Deep,
+    Light,
+    Rem,
+    Awake,
+    #[default]
+    Unknown,
+}
+
+impl Code for SleepPhase {
+    fn from_code(code: char) -> Self {
+        match code {
+            '1' => Self::Deep,
+            '2' => Self::Light,
+            '3' => Self::Rem,
+            '4' => Self::Awake,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// How much the ring moved during one interval of sleep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Movement {
+    Still,
+    Restless,
+    TossingAndTurning,
+    Active,
+    Unknown,
+}
+
+impl Code for Movement {
+    fn from_code(code: char) -> Self {
+        match code {
+            '1' => Self::Still,
+            '2' => Self::Restless,
+            '3' => Self::TossingAndTurning,
+            '4' => Self::Active,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Activity level during one 5-minute interval of the day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityClass {
+    NonWear,
+    Rest,
+    Inactive,
+    Low,
+    Medium,
+    High,
+    Unknown,
+}
+
+impl Code for ActivityClass {
+    fn from_code(code: char) -> Self {
+        match code {
+            '0' => Self::NonWear,
+            '1' => Self::Rest,
+            '2' => Self::Inactive,
+            '3' => Self::Low,
+            '4' => Self::Medium,
+            '5' => Self::High,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// `personal_info`: the account the token belongs to. Height is in meters,
+/// weight in kilograms.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PersonalInfo {
+    pub id: Option<String>,
+    pub email: Option<String>,
+    pub age: Option<i64>,
+    pub biological_sex: Option<String>,
+    pub height: Option<f64>,
+    pub weight: Option<f64>,
+}
+
+/// `ring_configuration`: one ring registered to the account.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RingConfiguration {
+    pub id: Option<String>,
+    /// e.g. `silver`, `stealth_black`, `brushed_titanium`.
+    pub color: Option<String>,
+    /// e.g. `heritage`, `horizon`.
+    pub design: Option<String>,
+    pub firmware_version: Option<String>,
+    /// e.g. `gen3`, `gen4`.
+    pub hardware_type: Option<String>,
+    /// When the ring was set up, RFC 3339.
+    pub set_up_at: Option<String>,
+    /// US ring size.
+    pub size: Option<i64>,
+}
+
+/// `daily_sleep`: the day's sleep score and its contributors.
 #[derive(Debug, Deserialize)]
 pub struct DailySleep {
     pub day: String,
     pub score: Option<i64>,
-    pub contributors: Option<serde_json::Value>,
+    pub contributors: Option<SleepContributors>,
 }
 
+/// `daily_readiness`: readiness score, temperature deviation and contributors.
 #[derive(Debug, Deserialize)]
 pub struct DailyReadiness {
     pub day: String,
     pub score: Option<i64>,
     pub temperature_deviation: Option<f64>,
     pub temperature_trend_deviation: Option<f64>,
-    pub contributors: Option<serde_json::Value>,
+    pub contributors: Option<ReadinessContributors>,
 }
 
+/// `daily_activity`: activity score, steps, calories and time per intensity.
+/// Durations are in seconds.
 #[derive(Debug, Deserialize)]
 pub struct DailyActivity {
     pub day: String,
@@ -43,10 +257,97 @@ pub struct DailyActivity {
     pub non_wear_time: Option<i64>,
     pub resting_time: Option<i64>,
     pub inactivity_alerts: Option<i64>,
-    pub class_5_min: Option<String>,
-    pub contributors: Option<serde_json::Value>,
+    /// Start of the activity day (4 AM local time); `class_5_min` counts from here.
+    pub timestamp: Option<String>,
+    pub class_5_min: Option<Sequence<ActivityClass>>,
+    pub contributors: Option<ActivityContributors>,
+}
+
+impl DailyActivity {
+    /// `class_5_min` with the start of each interval. Empty without a
+    /// parseable `timestamp`.
+    pub fn classes(&self) -> impl Iterator<Item = (DateTime<FixedOffset>, ActivityClass)> + '_ {
+        let start = self
+            .timestamp
+            .as_deref()
+            .and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+        start
+            .zip(self.class_5_min.as_ref())
+            .into_iter()
+            .flat_map(|(start, classes)| classes.timed(start, Duration::minutes(5)))
+    }
+}
+
+/// The 0–100 scores behind a daily score.
+pub trait Contributors {
+    /// Every contributor in the order the Oura app lists them, followed by
+    /// any the API added that this version doesn't know about.
+    fn scores(&self) -> Vec<(&str, Option<i64>)>;
+}
+
+macro_rules! contributors {
+    ($(#[$meta:meta])* $name:ident { $($field:ident),* $(,)? }) => {
+        $(#[$meta])*
+        ///
+        /// Serializes only the known contributors, so exported columns stay the
+        /// same from day to day; unknown ones are kept in `extra`.
+        #[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+        pub struct $name {
+            $(pub $field: Option<i64>,)*
+            #[serde(flatten, skip_serializing)]
+            pub extra: BTreeMap<String, serde_json::Value>,
+        }
+
+        impl Contributors for $name {
+            fn scores(&self) -> Vec<(&str, Option<i64>)> {
+                let known = [$((stringify!($field), self.$field)),*];
+                let extra = self.extra.iter().map(|(k, v)| (k.as_str(), v.as_i64()));
+                known.into_iter().chain(extra).collect()
+            }
+        }
+    };
 }
 
+contributors!(
+    /// What went into the sleep score.
+    SleepContributors {
+        total_sleep,
+        efficiency,
+        restfulness,
+        rem_sleep,
+        deep_sleep,
+        latency,
+        timing,
+    }
+);
+
+contributors!(
+    /// What went into the readiness score.
+    ReadinessContributors {
+        resting_heart_rate,
+        hrv_balance,
+        body_temperature,
+        recovery_index,
+        previous_night,
+        sleep_balance,
+        previous_day_activity,
+        activity_balance,
+    }
+);
+
+contributors!(
+    /// What went into the activity score.
+    ActivityContributors {
+        stay_active,
+        move_every_hour,
+        meet_daily_targets,
+        training_frequency,
+        training_volume,
+        recovery_time,
+    }
+);
+
+/// `sleep`: one detailed sleep period. Durations are in seconds.
 #[derive(Debug, Deserialize)]
 pub struct Sleep {
     pub day: String,
@@ -55,12 +356,12 @@ pub struct Sleep {
     pub period: Option<i64>,
     pub bedtime_start: Option<String>,
     pub bedtime_end: Option<String>,
-    pub sleep_phase_5_min: Option<String>,
-    pub sleep_phase_30_sec: Option<String>,
-    pub app_sleep_phase_5_min: Option<String>,
-    pub movement_30_sec: Option<String>,
-    pub heart_rate: Option<serde_json::Value>,
-    pub hrv: Option<serde_json::Value>,
+    pub sleep_phase_5_min: Option<Sequence<SleepPhase>>,
+    pub sleep_phase_30_sec: Option<Sequence<SleepPhase>>,
+    pub app_sleep_phase_5_min: Option<Sequence<SleepPhase>>,
+    pub movement_30_sec: Option<Sequence<Movement>>,
+    pub heart_rate: Option<TimeSeries>,
+    pub hrv: Option<TimeSeries>,
     pub total_sleep_duration: Option<i64>,
     pub time_in_bed: Option<i64>,
     pub efficiency: Option<i64>,
@@ -79,6 +380,211 @@ pub struct Sleep {
     pub low_battery_alert: Option<bool>,
 }
 
+impl Sleep {
+    fn start(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(self.bedtime_start.as_deref()?).ok()
+    }
+
+    /// `sleep_phase_5_min` with the start of each interval. Empty without a
+    /// parseable `bedtime_start`.
+    pub fn phases(&self) -> impl Iterator<Item = (DateTime<FixedOffset>, SleepPhase)> + '_ {
+        self.start()
+            .zip(self.sleep_phase_5_min.as_ref())
+            .into_iter()
+            .flat_map(|(start, phases)| phases.timed(start, Duration::minutes(5)))
+    }
+
+    /// `movement_30_sec` with the start of each interval.
+    pub fn movement(&self) -> impl Iterator<Item = (DateTime<FixedOffset>, Movement)> + '_ {
+        self.start()
+            .zip(self.movement_30_sec.as_ref())
+            .into_iter()
+            .flat_map(|(start, movement)| movement.timed(start, Duration::seconds(30)))
+    }
+}
+
+/// `heartrate`: one BPM reading. `source` is what the ring was doing at the
+/// time: `awake`, `rest`, `sleep`, `session`, `live` or `workout`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeartRateSample {
+    pub bpm: i64,
+    pub source: String,
+    /// RFC 3339, in UTC.
+    pub timestamp: String,
+}
+
+/// Samples belong to the local calendar day they were taken on.
+impl Dated for HeartRateSample {
+    fn day(&self) -> Cow<'_, str> {
+        match local_day(&self.timestamp) {
+            Some(day) => Cow::Owned(day.format("%Y-%m-%d").to_string()),
+            None => Cow::Borrowed(self.timestamp.get(..10).unwrap_or(&self.timestamp)),
+        }
+    }
+}
+
+/// The day an RFC 3339 `timestamp` falls on in the local time zone.
+pub fn local_day(timestamp: &str) -> Option<NaiveDate> {
+    let time = DateTime::parse_from_rfc3339(timestamp).ok()?;
+    Some(time.with_timezone(&Local).date_naive())
+}
+
+/// `daily_spo2`: blood oxygen during the night ending on `day`, and how often
+/// breathing was disturbed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DailySpo2 {
+    pub day: String,
+    pub spo2_percentage: Option<Spo2Percentage>,
+    /// Breathing disturbances per hour of sleep.
+    pub breathing_disturbance_index: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Spo2Percentage {
+    pub average: Option<f64>,
+}
+
+impl DailySpo2 {
+    /// Average SpO2 for the night, in percent.
+    pub fn average(&self) -> Option<f64> {
+        self.spo2_percentage.as_ref()?.average
+    }
+}
+
+/// `daily_cardiovascular_age`: estimated age of the vascular system.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DailyCardiovascularAge {
+    pub day: String,
+    pub vascular_age: Option<i64>,
+}
+
+/// `daily_resilience`: how well the body is handling stress.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DailyResilience {
+    pub day: String,
+    /// `limited`, `adequate`, `solid`, `strong` or `exceptional`.
+    pub level: Option<String>,
+    pub contributors: Option<ResilienceContributors>,
+}
+
+/// Each on a 0–100 scale.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResilienceContributors {
+    pub sleep_recovery: Option<f64>,
+    pub daytime_recovery: Option<f64>,
+    pub stress: Option<f64>,
+}
+
+/// `vO2_max`: estimated VO2 max in ml/kg/min, updated after qualifying walks.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Vo2Max {
+    pub day: String,
+    pub timestamp: Option<String>,
+    pub vo2_max: Option<f64>,
+}
+
+/// `workout`: one logged or auto-detected workout. `calories` is in kcal,
+/// `distance` in meters.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Workout {
+    pub day: String,
+    /// e.g. `walking`, `running`, `cycling`, `strength_training`.
+    pub activity: String,
+    pub calories: Option<f64>,
+    pub distance: Option<f64>,
+    pub start_datetime: String,
+    pub end_datetime: String,
+    /// `easy`, `moderate` or `hard`.
+    pub intensity: Option<String>,
+    pub label: Option<String>,
+    /// `manual`, `autodetected`, `confirmed` or `workout_heart_rate`.
+    pub source: Option<String>,
+}
+
+/// `session`: a guided or unguided meditation, breathing or rest session
+/// recorded in the app.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Session {
+    pub day: String,
+    pub start_datetime: String,
+    pub end_datetime: String,
+    /// `breathing`, `meditation`, `nap`, `relaxation`, `rest` or `body_status`.
+    #[serde(rename = "type")]
+    pub session_type: String,
+    /// `bad`, `worse`, `same`, `good` or `great`.
+    pub mood: Option<String>,
+    pub heart_rate: Option<TimeSeries>,
+    pub heart_rate_variability: Option<TimeSeries>,
+    pub motion_count: Option<TimeSeries>,
+}
+
+/// `tag`: the original tag format, superseded by [`EnhancedTag`] but still
+/// returned for older entries.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tag {
+    pub day: String,
+    pub timestamp: String,
+    pub text: Option<String>,
+    /// e.g. `tag_generic_alcohol`, `tag_generic_caffeine`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// `enhanced_tag`: a tagged event, either a moment or a span (travel, sickness).
+#[derive(Debug, Clone, Deserialize)]
+pub struct EnhancedTag {
+    /// e.g. `tag_generic_alcohol`, `tag_generic_late_meal`; `custom` for user-defined tags.
+    pub tag_type_code: Option<String>,
+    pub start_time: String,
+    pub end_time: Option<String>,
+    pub start_day: String,
+    pub end_day: Option<String>,
+    pub comment: Option<String>,
+    pub custom_name: Option<String>,
+}
+
+/// `rest_mode_period`: a stretch of Rest Mode, turned on while ill or
+/// recovering. `end_day` is unset while it's still active.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RestModePeriod {
+    pub start_day: String,
+    pub start_time: Option<String>,
+    pub end_day: Option<String>,
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub episodes: Vec<RestModeEpisode>,
+}
+
+/// Symptoms or causes tagged during Rest Mode.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RestModeEpisode {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub timestamp: String,
+}
+
+/// `sleep_time`: the recommended bedtime window for the night starting on `day`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SleepTime {
+    pub day: String,
+    pub optimal_bedtime: Option<OptimalBedtime>,
+    /// e.g. `earlier_bedtime`, `later_bedtime`, `follow_optimal_bedtime`,
+    /// `improve_efficiency`.
+    pub recommendation: Option<String>,
+    /// e.g. `optimal_found`, `not_enough_nights`, `bad_sleep_quality`.
+    pub status: Option<String>,
+}
+
+/// Bedtime window as seconds from midnight (negative is the evening before),
+/// in the timezone `day_tz` seconds east of UTC.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OptimalBedtime {
+    pub day_tz: i64,
+    pub start_offset: i64,
+    pub end_offset: i64,
+}
+
+/// `daily_stress`: time spent in high stress and high recovery, in seconds.
 #[derive(Debug, Deserialize)]
 pub struct DailyStress {
     pub day: String,
@@ -0,0 +1,314 @@
+//! OAuth2 authorization-code flow with PKCE, for Oura API applications.
+//!
+//! The user approves access in a browser, which redirects back to a listener
+//! on `127.0.0.1`; the code it receives is exchanged for an access token and
+//! a refresh token. Hand both to [`OuraClient::with_oauth`](crate::OuraClient::with_oauth)
+//! and the client refreshes the access token itself when the API rejects it.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration as StdDuration, Instant};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::Url;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{OuraError, Result};
+
+/// Where users approve access.
+pub const DEFAULT_AUTHORIZE_URL: &str = "https://cloud.ouraring.com/oauth/authorize";
+
+/// Where codes and refresh tokens are exchanged for access tokens.
+pub const DEFAULT_TOKEN_URL: &str = "https://api.ouraring.com/oauth/token";
+
+/// Where the authorization server sends the browser back to. It must match
+/// the redirect URI registered for the application exactly.
+pub const DEFAULT_REDIRECT_URI: &str = "http://127.0.0.1:8765/callback";
+
+/// Every scope the CLI reads from.
+pub const DEFAULT_SCOPES: [&str; 8] = [
+    "email",
+    "personal",
+    "daily",
+    "heartrate",
+    "workout",
+    "tag",
+    "session",
+    "spo2",
+];
+
+/// An application registered at cloud.ouraring.com.
+#[derive(Debug, Clone)]
+pub struct OAuthApp {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub authorize_url: String,
+    pub token_url: String,
+}
+
+/// Access and refresh token from the token endpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Space-separated scopes the user granted.
+    pub scope: Option<String>,
+}
+
+/// The token endpoint's JSON body.
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<i64>,
+    scope: Option<String>,
+}
+
+/// A PKCE verifier and its S256 challenge.
+#[derive(Debug, Clone)]
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+impl Pkce {
+    pub fn new() -> Result<Self> {
+        let verifier = random_string()?;
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        Ok(Self {
+            verifier,
+            challenge,
+        })
+    }
+}
+
+/// 32 random bytes, base64url-encoded: for PKCE verifiers and `state`.
+pub fn random_string() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| OuraError::OAuth(format!("no randomness available: {err}")))?;
+    Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+impl OAuthApp {
+    pub fn new(client_id: impl Into<String>, client_secret: Option<String>) -> Self {
+        Self {
+            client_id: client_id.into(),
+            client_secret,
+            authorize_url: DEFAULT_AUTHORIZE_URL.to_string(),
+            token_url: DEFAULT_TOKEN_URL.to_string(),
+        }
+    }
+
+    /// Use another authorization server (e.g. a local stand-in).
+    pub fn with_endpoints(
+        mut self,
+        authorize_url: impl Into<String>,
+        token_url: impl Into<String>,
+    ) -> Self {
+        self.authorize_url = authorize_url.into();
+        self.token_url = token_url.into();
+        self
+    }
+
+    /// The page to send the user to.
+    pub fn authorize_url(
+        &self,
+        redirect_uri: &str,
+        scopes: &[&str],
+        state: &str,
+        pkce: &Pkce,
+    ) -> Result<String> {
+        let url = Url::parse_with_params(
+            &self.authorize_url,
+            [
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", redirect_uri),
+                ("scope", &scopes.join(" ")),
+                ("state", state),
+                ("code_challenge", &pkce.challenge),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|err| OuraError::OAuth(format!("invalid authorize URL: {err}")))?;
+        Ok(url.into())
+    }
+
+    /// Trades the code from the redirect for tokens.
+    pub fn exchange_code(&self, code: &str, redirect_uri: &str, pkce: &Pkce) -> Result<OAuthToken> {
+        self.token_request(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("code_verifier", &pkce.verifier),
+        ])
+    }
+
+    /// Gets a fresh access token. The server may rotate the refresh token;
+    /// if it doesn't, the old one is kept.
+    pub fn refresh(&self, refresh_token: &str) -> Result<OAuthToken> {
+        let mut token = self.token_request(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])?;
+        token
+            .refresh_token
+            .get_or_insert_with(|| refresh_token.to_string());
+        Ok(token)
+    }
+
+    fn token_request(&self, params: &[(&str, &str)]) -> Result<OAuthToken> {
+        let mut form = params.to_vec();
+        form.push(("client_id", &self.client_id));
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let resp = Client::new().post(&self.token_url).form(&form).send()?;
+        let status = resp.status();
+        // A revoked or expired grant can only be fixed by signing in again.
+        if !status.is_success() {
+            let body = resp.text().unwrap_or_default();
+            return Err(OuraError::OAuth(format!(
+                "token endpoint returned {status}: {}",
+                body.trim()
+            )));
+        }
+        let token: TokenResponse = serde_json::from_str(&resp.text()?)?;
+        Ok(OAuthToken {
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: token
+                .expires_in
+                .map(|secs| Utc::now() + Duration::seconds(secs)),
+            scope: token.scope,
+        })
+    }
+}
+
+/// How long the browser gets to send its request line once connected.
+/// Preconnect sockets that never send one are dropped after this.
+const REQUEST_TIMEOUT: StdDuration = StdDuration::from_secs(2);
+
+/// How often the listener checks for a connection while waiting.
+const ACCEPT_POLL: StdDuration = StdDuration::from_millis(100);
+
+/// How long `oura auth login --oauth` waits for the user to approve access.
+pub const DEFAULT_LOGIN_TIMEOUT: StdDuration = StdDuration::from_secs(300);
+
+/// A one-shot HTTP listener on the loopback address that catches the
+/// browser's redirect back from the authorization page.
+pub struct Loopback {
+    listener: TcpListener,
+    redirect_uri: String,
+    path: String,
+}
+
+impl Loopback {
+    /// Listens on the host and port of `redirect_uri`, which must be a plain
+    /// `http` URI on `127.0.0.1`, `localhost` or `[::1]`. Port 0 picks a
+    /// free port (for servers that accept any loopback port).
+    pub fn bind(redirect_uri: &str) -> Result<Self> {
+        let invalid = |why: &str| OuraError::OAuth(format!("redirect URI {redirect_uri} {why}"));
+        let mut url = Url::parse(redirect_uri).map_err(|_| invalid("is not a valid URL"))?;
+        let host = match url.host_str() {
+            Some(host @ ("127.0.0.1" | "localhost" | "[::1]")) if url.scheme() == "http" => {
+                host.trim_matches(['[', ']']).to_string()
+            }
+            _ => return Err(invalid("must be http://127.0.0.1:PORT/...")),
+        };
+        let port = url
+            .port()
+            .ok_or_else(|| invalid("needs an explicit port"))?;
+        let listener = TcpListener::bind((host.as_str(), port))?;
+        if port == 0 {
+            let _ = url.set_port(Some(listener.local_addr()?.port()));
+        }
+        Ok(Self {
+            path: url.path().to_string(),
+            redirect_uri: url.into(),
+            listener,
+        })
+    }
+
+    /// The URI to send in the authorization request, with the bound port.
+    pub fn redirect_uri(&self) -> &str {
+        &self.redirect_uri
+    }
+
+    /// Waits for the redirect and returns its `code`, after checking `state`.
+    /// Requests for other paths (e.g. `/favicon.ico`), connections that send
+    /// nothing and malformed requests are ignored. Gives up after `timeout`.
+    pub fn wait_for_code(&self, state: &str, timeout: StdDuration) -> Result<String> {
+        let deadline = Instant::now() + timeout;
+        self.listener.set_nonblocking(true)?;
+        loop {
+            let mut stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(OuraError::OAuth(format!(
+                            "no redirect from the browser within {}s",
+                            timeout.as_secs()
+                        )));
+                    }
+                    std::thread::sleep(ACCEPT_POLL);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let Some(target) = request_target(&stream) else {
+                continue;
+            };
+            let Ok(url) = Url::parse(&format!("http://127.0.0.1{target}")) else {
+                continue;
+            };
+            if url.path() != self.path {
+                let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n");
+                continue;
+            }
+
+            let param = |name: &str| {
+                url.query_pairs()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+            let outcome = if let Some(error) = param("error") {
+                Err(OuraError::OAuth(format!("authorization denied: {error}")))
+            } else if param("state").as_deref() != Some(state) {
+                Err(OuraError::OAuth("state mismatch in the redirect".into()))
+            } else {
+                param("code").ok_or_else(|| OuraError::OAuth("no code in the redirect".into()))
+            };
+
+            let message = match &outcome {
+                Ok(_) => "Authorized. You can close this window and return to the terminal.",
+                Err(_) => "Authorization failed. See the terminal for details.",
+            };
+            let _ = write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{message}",
+                message.len()
+            );
+            return outcome;
+        }
+    }
+}
+
+/// The target of a `GET` request line, or `None` if the connection sends
+/// nothing usable within `REQUEST_TIMEOUT`.
+fn request_target(stream: &TcpStream) -> Option<String> {
+    stream.set_nonblocking(false).ok()?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT)).ok()?;
+    let mut request_line = String::new();
+    BufReader::new(stream).read_line(&mut request_line).ok()?;
+    match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["GET", target, _] if target.starts_with('/') => Some(target.to_string()),
+        _ => None,
+    }
+}
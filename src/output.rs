@@ -0,0 +1,636 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use oura_cli::analysis::{
+    Analysis, HeartRateStats, WorkoutTotals, before_after, heart_rate_by_source, in_rest_mode,
+    primary_sleep, session_duration, workout_duration, workout_totals,
+};
+use oura_cli::models::{
+    ActivityContributors, DailyActivity, DailyCardiovascularAge, DailyReadiness, DailyResilience,
+    DailySleep, DailySpo2, DailyStress, HeartRateSample, ReadinessContributors, RestModePeriod,
+    Session, Sleep, SleepContributors, SleepPhase, Vo2Max, Workout,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// How commands print their results. Everything except `text` is meant for scripts:
+/// JSON emits the documented structure, CSV/TSV one row per record.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+impl Format {
+    pub fn is_text(self) -> bool {
+        self == Format::Text
+    }
+}
+
+/// Writes `value` in a machine format. For CSV/TSV an array becomes one row
+/// per element and a single object one row; nested objects flatten to
+/// `parent_child` columns.
+pub fn emit<T: Serialize>(format: Format, value: &T) -> Result<()> {
+    let value = serde_json::to_value(value)?;
+    match format {
+        Format::Text | Format::Json => println!("{}", serde_json::to_string_pretty(&value)?),
+        Format::Csv => print_table(&value, ','),
+        Format::Tsv => print_table(&value, '\t'),
+    }
+    Ok(())
+}
+
+/// Like [`emit`] for a list of rows, but CSV/TSV still get a header when
+/// `rows` is empty, taken from a default row.
+pub fn emit_rows<R: Serialize + Default>(format: Format, rows: &[R]) -> Result<()> {
+    if !rows.is_empty() || !matches!(format, Format::Csv | Format::Tsv) {
+        return emit(format, &rows);
+    }
+    let header = flatten(&serde_json::to_value(R::default())?);
+    let sep = if format == Format::Csv { ',' } else { '\t' };
+    let columns: Vec<String> = header.keys().map(|c| escape(c, sep)).collect();
+    println!("{}", columns.join(&sep.to_string()));
+    Ok(())
+}
+
+fn print_table(value: &Value, sep: char) {
+    let rows: Vec<Map<String, Value>> = match value {
+        Value::Array(items) => items.iter().map(flatten).collect(),
+        other => vec![flatten(other)],
+    };
+
+    let mut columns: Vec<&str> = Vec::new();
+    for row in &rows {
+        for key in row.keys() {
+            if !columns.contains(&key.as_str()) {
+                columns.push(key);
+            }
+        }
+    }
+
+    let line = |cells: Vec<String>| cells.join(&sep.to_string());
+    println!("{}", line(columns.iter().map(|c| escape(c, sep)).collect()));
+    for row in &rows {
+        let cells = columns
+            .iter()
+            .map(|c| escape(&cell(row.get(*c)), sep))
+            .collect();
+        println!("{}", line(cells));
+    }
+}
+
+fn flatten(value: &Value) -> Map<String, Value> {
+    let mut out = Map::new();
+    match value {
+        Value::Object(obj) => flatten_into(&mut out, "", obj),
+        other => {
+            out.insert("value".into(), other.clone());
+        }
+    }
+    out
+}
+
+fn flatten_into(out: &mut Map<String, Value>, prefix: &str, obj: &Map<String, Value>) {
+    for (key, value) in obj {
+        let name = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}_{key}")
+        };
+        match value {
+            Value::Object(inner) => flatten_into(out, &name, inner),
+            other => {
+                out.insert(name, other.clone());
+            }
+        }
+    }
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn escape(field: &str, sep: char) -> String {
+    if sep == '\t' {
+        return field.replace(['\t', '\n', '\r'], " ");
+    }
+    if field.contains([sep, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// --- Per-command structures ---
+//
+// Per-day commands emit one row per day in the requested range, with null
+// fields for days that have no data.
+
+#[derive(Serialize)]
+pub struct ScoresRow {
+    pub date: String,
+    pub sleep_score: Option<i64>,
+    pub readiness_score: Option<i64>,
+    pub activity_score: Option<i64>,
+    pub temperature_deviation: Option<f64>,
+}
+
+impl ScoresRow {
+    pub fn new(
+        date: &str,
+        sleep: Option<&DailySleep>,
+        readiness: Option<&DailyReadiness>,
+        activity: Option<&DailyActivity>,
+    ) -> Self {
+        Self {
+            date: date.to_string(),
+            sleep_score: sleep.and_then(|s| s.score),
+            readiness_score: readiness.and_then(|r| r.score),
+            activity_score: activity.and_then(|a| a.score),
+            temperature_deviation: readiness.and_then(|r| r.temperature_deviation),
+        }
+    }
+}
+
+/// Durations are in seconds.
+#[derive(Serialize)]
+pub struct SleepRow {
+    pub date: String,
+    pub score: Option<i64>,
+    pub period_type: Option<String>,
+    pub bedtime_start: Option<String>,
+    pub bedtime_end: Option<String>,
+    pub total_sleep_duration: Option<i64>,
+    pub time_in_bed: Option<i64>,
+    pub efficiency: Option<i64>,
+    pub latency: Option<i64>,
+    pub deep_sleep_duration: Option<i64>,
+    pub light_sleep_duration: Option<i64>,
+    pub rem_sleep_duration: Option<i64>,
+    pub awake_time: Option<i64>,
+    pub restless_periods: Option<i64>,
+    pub average_hrv: Option<i64>,
+    pub average_heart_rate: Option<f64>,
+    pub lowest_heart_rate: Option<i64>,
+    pub contributors: SleepContributors,
+}
+
+impl SleepRow {
+    pub fn new(date: &str, daily: Option<&DailySleep>, records: &[Sleep]) -> Self {
+        let s = primary_sleep(records);
+        Self {
+            date: date.to_string(),
+            score: daily.and_then(|d| d.score),
+            period_type: s.and_then(|s| s.sleep_type.clone()),
+            bedtime_start: s.and_then(|s| s.bedtime_start.clone()),
+            bedtime_end: s.and_then(|s| s.bedtime_end.clone()),
+            total_sleep_duration: s.and_then(|s| s.total_sleep_duration),
+            time_in_bed: s.and_then(|s| s.time_in_bed),
+            efficiency: s.and_then(|s| s.efficiency),
+            latency: s.and_then(|s| s.latency),
+            deep_sleep_duration: s.and_then(|s| s.deep_sleep_duration),
+            light_sleep_duration: s.and_then(|s| s.light_sleep_duration),
+            rem_sleep_duration: s.and_then(|s| s.rem_sleep_duration),
+            awake_time: s.and_then(|s| s.awake_time),
+            restless_periods: s.and_then(|s| s.restless_periods),
+            average_hrv: s.and_then(|s| s.average_hrv),
+            average_heart_rate: s.and_then(|s| s.average_heart_rate),
+            lowest_heart_rate: s.and_then(|s| s.lowest_heart_rate),
+            contributors: daily
+                .and_then(|d| d.contributors.clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReadinessRow {
+    pub date: String,
+    pub score: Option<i64>,
+    pub temperature_deviation: Option<f64>,
+    pub temperature_trend_deviation: Option<f64>,
+    pub contributors: ReadinessContributors,
+}
+
+impl ReadinessRow {
+    pub fn new(date: &str, r: Option<&DailyReadiness>) -> Self {
+        Self {
+            date: date.to_string(),
+            score: r.and_then(|r| r.score),
+            temperature_deviation: r.and_then(|r| r.temperature_deviation),
+            temperature_trend_deviation: r.and_then(|r| r.temperature_trend_deviation),
+            contributors: r.and_then(|r| r.contributors.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+/// Times are in seconds, distance in meters.
+#[derive(Serialize)]
+pub struct ActivityRow {
+    pub date: String,
+    pub score: Option<i64>,
+    pub steps: Option<i64>,
+    pub active_calories: Option<i64>,
+    pub total_calories: Option<i64>,
+    pub equivalent_walking_distance: Option<i64>,
+    pub high_activity_time: Option<i64>,
+    pub medium_activity_time: Option<i64>,
+    pub low_activity_time: Option<i64>,
+    pub sedentary_time: Option<i64>,
+    pub contributors: ActivityContributors,
+}
+
+impl ActivityRow {
+    pub fn new(date: &str, a: Option<&DailyActivity>) -> Self {
+        Self {
+            date: date.to_string(),
+            score: a.and_then(|a| a.score),
+            steps: a.and_then(|a| a.steps),
+            active_calories: a.and_then(|a| a.active_calories),
+            total_calories: a.and_then(|a| a.total_calories),
+            equivalent_walking_distance: a.and_then(|a| a.equivalent_walking_distance),
+            high_activity_time: a.and_then(|a| a.high_activity_time),
+            medium_activity_time: a.and_then(|a| a.medium_activity_time),
+            low_activity_time: a.and_then(|a| a.low_activity_time),
+            sedentary_time: a.and_then(|a| a.sedentary_time),
+            contributors: a.and_then(|a| a.contributors.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct HrvRow {
+    pub date: String,
+    pub average_hrv: Option<i64>,
+    pub average_heart_rate: Option<f64>,
+    pub lowest_heart_rate: Option<i64>,
+    pub average_breath: Option<f64>,
+}
+
+impl HrvRow {
+    pub fn new(date: &str, records: &[Sleep]) -> Self {
+        let s = primary_sleep(records);
+        Self {
+            date: date.to_string(),
+            average_hrv: s.and_then(|s| s.average_hrv),
+            average_heart_rate: s.and_then(|s| s.average_heart_rate),
+            lowest_heart_rate: s.and_then(|s| s.lowest_heart_rate),
+            average_breath: s.and_then(|s| s.average_breath),
+        }
+    }
+}
+
+/// SpO2 is the night's average, in percent.
+#[derive(Serialize)]
+pub struct Spo2Row {
+    pub date: String,
+    pub spo2: Option<f64>,
+    pub breathing_disturbance_index: Option<i64>,
+}
+
+impl Spo2Row {
+    pub fn new(date: &str, s: Option<&DailySpo2>) -> Self {
+        Self {
+            date: date.to_string(),
+            spo2: s.and_then(DailySpo2::average),
+            breathing_disturbance_index: s.and_then(|s| s.breathing_disturbance_index),
+        }
+    }
+}
+
+/// One day of the fitness window; each value is only set on days it was measured.
+#[derive(Serialize)]
+pub struct FitnessRow {
+    pub date: String,
+    pub vascular_age: Option<i64>,
+    pub resilience_level: Option<String>,
+    pub sleep_recovery: Option<f64>,
+    pub daytime_recovery: Option<f64>,
+    pub stress: Option<f64>,
+    pub vo2_max: Option<f64>,
+}
+
+impl FitnessRow {
+    pub fn new(
+        date: &str,
+        cardiovascular_age: Option<&DailyCardiovascularAge>,
+        resilience: Option<&DailyResilience>,
+        vo2_max: Option<&Vo2Max>,
+    ) -> Self {
+        let contributors = resilience.and_then(|r| r.contributors.as_ref());
+        Self {
+            date: date.to_string(),
+            vascular_age: cardiovascular_age.and_then(|c| c.vascular_age),
+            resilience_level: resilience.and_then(|r| r.level.clone()),
+            sleep_recovery: contributors.and_then(|c| c.sleep_recovery),
+            daytime_recovery: contributors.and_then(|c| c.daytime_recovery),
+            stress: contributors.and_then(|c| c.stress),
+            vo2_max: vo2_max.and_then(|v| v.vo2_max),
+        }
+    }
+}
+
+/// Times are in seconds.
+#[derive(Serialize)]
+pub struct StressRow {
+    pub date: String,
+    pub day_summary: Option<String>,
+    pub stress_high: Option<i64>,
+    pub recovery_high: Option<i64>,
+}
+
+impl StressRow {
+    pub fn new(date: &str, s: Option<&DailyStress>) -> Self {
+        Self {
+            date: date.to_string(),
+            day_summary: s.and_then(|s| s.day_summary.clone()),
+            stress_high: s.and_then(|s| s.stress_high),
+            recovery_high: s.and_then(|s| s.recovery_high),
+        }
+    }
+}
+
+/// One workout. Duration is in seconds, calories in kcal, distance in meters.
+#[derive(Default, Serialize)]
+pub struct WorkoutRow<'a> {
+    pub date: &'a str,
+    pub start: &'a str,
+    pub end: &'a str,
+    pub activity: &'a str,
+    pub label: Option<&'a str>,
+    pub intensity: Option<&'a str>,
+    pub duration: Option<i64>,
+    pub calories: Option<f64>,
+    pub distance: Option<f64>,
+    pub source: Option<&'a str>,
+}
+
+pub fn workout_rows(workouts: &[Workout]) -> Vec<WorkoutRow<'_>> {
+    let mut rows: Vec<_> = workouts
+        .iter()
+        .map(|w| WorkoutRow {
+            date: &w.day,
+            start: &w.start_datetime,
+            end: &w.end_datetime,
+            activity: &w.activity,
+            label: w.label.as_deref(),
+            intensity: w.intensity.as_deref(),
+            duration: workout_duration(w),
+            calories: w.calories,
+            distance: w.distance,
+            source: w.source.as_deref(),
+        })
+        .collect();
+    rows.sort_by(|a, b| a.start.cmp(b.start));
+    rows
+}
+
+/// Workouts in the range plus totals per activity type.
+#[derive(Serialize)]
+pub struct Workouts<'a> {
+    pub workouts: Vec<WorkoutRow<'a>>,
+    pub totals: Vec<WorkoutTotals>,
+}
+
+impl<'a> Workouts<'a> {
+    pub fn new(workouts: &'a [Workout]) -> Self {
+        Self {
+            workouts: workout_rows(workouts),
+            totals: workout_totals(workouts),
+        }
+    }
+}
+
+/// One session. Duration is in seconds; `*_before`/`*_after` average the
+/// first and last minute.
+#[derive(Default, Serialize)]
+pub struct SessionRow<'a> {
+    pub date: &'a str,
+    pub start: &'a str,
+    pub end: &'a str,
+    #[serde(rename = "type")]
+    pub session_type: &'a str,
+    pub duration: Option<i64>,
+    pub mood: Option<&'a str>,
+    pub heart_rate_before: Option<f64>,
+    pub heart_rate_after: Option<f64>,
+    pub hrv_before: Option<f64>,
+    pub hrv_after: Option<f64>,
+}
+
+pub fn session_rows(sessions: &[Session]) -> Vec<SessionRow<'_>> {
+    let mut rows: Vec<_> = sessions
+        .iter()
+        .map(|s| {
+            let hr = s.heart_rate.as_ref().and_then(before_after);
+            let hrv = s.heart_rate_variability.as_ref().and_then(before_after);
+            SessionRow {
+                date: &s.day,
+                start: &s.start_datetime,
+                end: &s.end_datetime,
+                session_type: &s.session_type,
+                duration: session_duration(s),
+                mood: s.mood.as_deref(),
+                heart_rate_before: hr.map(|c| c.before),
+                heart_rate_after: hr.map(|c| c.after),
+                hrv_before: hrv.map(|c| c.before),
+                hrv_after: hrv.map(|c| c.after),
+            }
+        })
+        .collect();
+    rows.sort_by(|a, b| a.start.cmp(b.start));
+    rows
+}
+
+/// One heart rate reading; CSV/TSV emit these directly.
+#[derive(Default, Serialize)]
+pub struct SampleRow<'a> {
+    pub timestamp: &'a str,
+    pub bpm: i64,
+    pub source: &'a str,
+}
+
+pub fn sample_rows(samples: &[HeartRateSample]) -> Vec<SampleRow<'_>> {
+    samples
+        .iter()
+        .map(|s| SampleRow {
+            timestamp: &s.timestamp,
+            bpm: s.bpm,
+            source: &s.source,
+        })
+        .collect()
+}
+
+/// A day of heart rate: the per-source summary plus every sample.
+#[derive(Serialize)]
+pub struct HeartRateDay<'a> {
+    pub date: String,
+    pub sources: Vec<HeartRateStats>,
+    pub samples: Vec<SampleRow<'a>>,
+}
+
+impl<'a> HeartRateDay<'a> {
+    pub fn new(date: &str, samples: &'a [HeartRateSample]) -> Self {
+        Self {
+            date: date.to_string(),
+            sources: heart_rate_by_source(samples),
+            samples: sample_rows(samples),
+        }
+    }
+}
+
+/// One analyze finding, for CSV/TSV (JSON emits the whole `Analysis`).
+#[derive(Default, Serialize)]
+pub struct FlagRow<'a> {
+    pub date: Option<&'a str>,
+    pub kind: &'a str,
+    pub severity: oura_cli::analysis::Severity,
+    pub message: &'a str,
+    pub value: Option<f64>,
+    pub at: Option<&'a str>,
+    /// Related tags, `; `-separated.
+    pub notes: String,
+}
+
+pub fn flag_rows(analysis: &Analysis) -> Vec<FlagRow<'_>> {
+    analysis
+        .flags
+        .iter()
+        .map(|f| FlagRow {
+            date: analysis.day.as_deref(),
+            kind: f.kind,
+            severity: f.severity,
+            message: &f.message,
+            value: f.value,
+            at: f.at.as_deref(),
+            notes: f.notes.join("; "),
+        })
+        .collect()
+}
+
+/// One 5-minute hypnogram interval.
+#[derive(Default, Serialize)]
+pub struct PhaseRow {
+    pub time: String,
+    pub phase: SleepPhase,
+}
+
+pub fn phase_rows(records: &[Sleep]) -> Vec<PhaseRow> {
+    let Some(s) = primary_sleep(records) else {
+        return Vec::new();
+    };
+    s.phases()
+        .map(|(at, phase)| PhaseRow {
+            time: at.to_rfc3339(),
+            phase,
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct TrendRow {
+    pub date: String,
+    pub sleep: Option<i64>,
+    pub readiness: Option<i64>,
+    pub activity: Option<i64>,
+    /// Average overnight SpO2, percent.
+    pub spo2: Option<f64>,
+    /// Breathing disturbances per hour.
+    pub bdi: Option<i64>,
+    /// The day fell inside a Rest Mode period.
+    pub rest_mode: bool,
+}
+
+/// A row from one of several accounts, for `--all-accounts`.
+#[derive(Serialize)]
+pub struct AccountRow<'a, T> {
+    pub account: &'a str,
+    #[serde(flatten)]
+    pub row: T,
+}
+
+/// Score trend: one row per day plus the average of the days that have a score.
+#[derive(Serialize)]
+pub struct Trend {
+    pub days: Vec<TrendRow>,
+    pub average: TrendRow,
+}
+
+impl Trend {
+    pub fn new(
+        days: &[NaiveDate],
+        sleep: &[DailySleep],
+        readiness: &[DailyReadiness],
+        activity: &[DailyActivity],
+        spo2: &[DailySpo2],
+        rest_mode: &[RestModePeriod],
+    ) -> Self {
+        let sleep_map: HashMap<&str, Option<i64>> =
+            sleep.iter().map(|s| (s.day.as_str(), s.score)).collect();
+        let readiness_map: HashMap<&str, Option<i64>> = readiness
+            .iter()
+            .map(|r| (r.day.as_str(), r.score))
+            .collect();
+        let activity_map: HashMap<&str, Option<i64>> =
+            activity.iter().map(|a| (a.day.as_str(), a.score)).collect();
+        let spo2_map: HashMap<&str, &DailySpo2> =
+            spo2.iter().map(|s| (s.day.as_str(), s)).collect();
+
+        let rows: Vec<TrendRow> = days
+            .iter()
+            .map(|date| {
+                let day = date.to_string();
+                TrendRow {
+                    sleep: sleep_map.get(day.as_str()).copied().flatten(),
+                    readiness: readiness_map.get(day.as_str()).copied().flatten(),
+                    activity: activity_map.get(day.as_str()).copied().flatten(),
+                    spo2: spo2_map.get(day.as_str()).and_then(|s| s.average()),
+                    bdi: spo2_map
+                        .get(day.as_str())
+                        .and_then(|s| s.breathing_disturbance_index),
+                    rest_mode: in_rest_mode(rest_mode, &day),
+                    date: day,
+                }
+            })
+            .collect();
+
+        let avg = |score: fn(&TrendRow) -> Option<i64>| {
+            let values: Vec<i64> = rows.iter().filter_map(score).collect();
+            (!values.is_empty()).then(|| values.iter().sum::<i64>() / values.len() as i64)
+        };
+        let average = TrendRow {
+            date: "average".to_string(),
+            sleep: avg(|r| r.sleep),
+            readiness: avg(|r| r.readiness),
+            activity: avg(|r| r.activity),
+            spo2: {
+                let values: Vec<f64> = rows.iter().filter_map(|r| r.spo2).collect();
+                (!values.is_empty()).then(|| {
+                    (values.iter().sum::<f64>() / values.len() as f64 * 10.0).round() / 10.0
+                })
+            },
+            bdi: avg(|r| r.bdi),
+            rest_mode: false,
+        };
+
+        Self {
+            days: rows,
+            average,
+        }
+    }
+
+    /// Days followed by the average, for CSV/TSV.
+    pub fn table(&self) -> Vec<&TrendRow> {
+        self.days.iter().chain([&self.average]).collect()
+    }
+}
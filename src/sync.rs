@@ -0,0 +1,141 @@
+//! Incremental mirroring of account history into the local cache.
+
+use chrono::{Days, Local, NaiveDate};
+
+use crate::async_client::AsyncOuraClient;
+use crate::error::Result;
+
+/// An endpoint `oura sync` archives, and how many days to request at a time.
+pub struct Endpoint {
+    pub name: &'static str,
+    pub chunk_days: u64,
+}
+
+const fn daily(name: &'static str) -> Endpoint {
+    Endpoint {
+        name,
+        chunk_days: 90,
+    }
+}
+
+/// Every day-keyed endpoint worth archiving.
+pub const ENDPOINTS: &[Endpoint] = &[
+    daily("daily_sleep"),
+    daily("sleep"),
+    daily("daily_readiness"),
+    daily("daily_activity"),
+    daily("daily_stress"),
+    daily("daily_spo2"),
+    daily("daily_resilience"),
+    daily("daily_cardiovascular_age"),
+    daily("vO2_max"),
+    daily("sleep_time"),
+    daily("rest_mode_period"),
+    daily("workout"),
+    daily("session"),
+    daily("tag"),
+    daily("enhanced_tag"),
+    // 5-minute samples are large; the API also caps heartrate ranges at 30 days.
+    Endpoint {
+        name: "heartrate",
+        chunk_days: 7,
+    },
+];
+
+/// How far back a first sync goes when no `since` is given.
+pub const DEFAULT_HISTORY_DAYS: u64 = 365;
+
+/// Progress report for one stored chunk.
+pub struct SyncedChunk {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub records: usize,
+}
+
+impl AsyncOuraClient {
+    /// Archives `endpoint` from `since` through today, skipping the span a
+    /// previous sync already covered. Without `since`, starts where the
+    /// previous sync began, or `DEFAULT_HISTORY_DAYS` ago on a first sync.
+    /// The synced span is saved after every chunk, so an interrupted sync
+    /// resumes where it stopped. Days inside the refresh window are stored
+    /// but not marked synced, so they're fetched again next time. Chunks of
+    /// one endpoint are fetched in order; sync several endpoints at once to
+    /// overlap requests.
+    pub async fn sync(
+        &self,
+        endpoint: &Endpoint,
+        since: Option<NaiveDate>,
+        mut on_chunk: impl FnMut(&SyncedChunk),
+    ) -> Result<usize> {
+        let name = endpoint.name;
+        let today = Local::now().date_naive();
+        let synced = self.cache()?.synced_range(name)?;
+        let since = since.or(synced.map(|(from, _)| from)).unwrap_or_else(|| {
+            today
+                .checked_sub_days(Days::new(DEFAULT_HISTORY_DAYS))
+                .unwrap_or(today)
+        });
+        let last_settled = self.settled_before().pred_opt().unwrap_or(since);
+        let mut total = 0;
+
+        // Backfill older history newest-first so the synced span stays contiguous.
+        if let Some((from, to)) = synced.filter(|(from, _)| since < *from) {
+            let mut end = from.pred_opt().unwrap_or(from);
+            while end >= since {
+                let start = chunk_back(end, endpoint.chunk_days).max(since);
+                total += self.sync_chunk(name, start, end, &mut on_chunk).await?;
+                self.cache()?.set_synced_range(name, start, to)?;
+                let Some(prev) = start.pred_opt() else { break };
+                end = prev;
+            }
+        }
+
+        let synced = self.cache()?.synced_range(name)?;
+        let (mut from, mut start) = match synced {
+            Some((from, to)) => (Some(from), to.succ_opt().unwrap_or(to)),
+            None => (None, since),
+        };
+        while start <= today {
+            let end = chunk_forward(start, endpoint.chunk_days).min(today);
+            total += self.sync_chunk(name, start, end, &mut on_chunk).await?;
+            let settled_end = end.min(last_settled);
+            if settled_end >= start {
+                let first = *from.get_or_insert(start);
+                self.cache()?.set_synced_range(name, first, settled_end)?;
+            }
+            let Some(next) = end.succ_opt() else { break };
+            start = next;
+        }
+
+        Ok(total)
+    }
+
+    /// Stores one chunk and reports it; returns its record count.
+    async fn sync_chunk(
+        &self,
+        name: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+        on_chunk: &mut impl FnMut(&SyncedChunk),
+    ) -> Result<usize> {
+        let (by_day, undated) = self.fetch_into_cache(name, start, end).await?;
+        let records = by_day.values().map(Vec::len).sum::<usize>() + undated.len();
+        on_chunk(&SyncedChunk {
+            start,
+            end,
+            records,
+        });
+        Ok(records)
+    }
+}
+
+fn chunk_forward(start: NaiveDate, days: u64) -> NaiveDate {
+    start
+        .checked_add_days(Days::new(days.saturating_sub(1)))
+        .unwrap_or(start)
+}
+
+fn chunk_back(end: NaiveDate, days: u64) -> NaiveDate {
+    end.checked_sub_days(Days::new(days.saturating_sub(1)))
+        .unwrap_or(end)
+}
@@ -0,0 +1,1602 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{Days, Local};
+use common::{Env, MockServer, Reply, run, stdout};
+
+const DAY: &str = "2024-02-13";
+
+#[test]
+fn scores_fetches_all_three_daily_endpoints() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["scores", DAY]));
+
+    assert!(out.contains("Sleep"));
+    assert!(out.contains("82"));
+    assert!(out.contains("79"));
+    assert!(out.contains("91"));
+    assert!(out.contains("Readiness contributors:"));
+    assert!(out.contains("HRV Balance"));
+
+    for endpoint in ["daily_sleep", "daily_readiness", "daily_activity"] {
+        let requests = server.requests_to(endpoint);
+        assert_eq!(requests.len(), 1, "{endpoint}");
+        assert_eq!(requests[0].param("start_date"), Some(DAY));
+        assert_eq!(requests[0].param("end_date"), Some("2024-02-14"));
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some("Bearer test-token")
+        );
+    }
+}
+
+#[test]
+fn default_command_is_scores() {
+    let server = MockServer::fixtures();
+    stdout(&run(&server, &[]));
+    assert_eq!(server.requests_to("daily_sleep").len(), 1);
+    assert_eq!(server.requests_to("daily_readiness").len(), 1);
+    assert_eq!(server.requests_to("daily_activity").len(), 1);
+}
+
+#[test]
+fn sleep_shows_breakdown() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["sleep", DAY]));
+    assert!(out.contains("Total Sleep: 7h 00m"));
+    assert!(out.contains("Efficiency:  89%"));
+    assert!(out.contains("Avg HRV:     45 ms"));
+    assert!(out.contains("23:15 → 07:05"));
+}
+
+#[test]
+fn analyze_flags_issues() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["analyze", DAY]));
+    assert!(out.contains("Analysis — 2024-02-13"));
+    assert!(out.contains("Woke at 23:15 for 10 min"));
+    assert!(out.contains("Low deep sleep: 50m"));
+    assert!(out.contains("High restlessness: 24 periods"));
+    assert!(out.contains("Temp elevated: +0.6°C"));
+    assert!(out.contains("Below your baseline (-7 pts)"));
+}
+
+#[test]
+fn hypnogram_renders_bar_and_legend() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["hypnogram", DAY]));
+    assert!(out.contains("23:15 -> 07:05"));
+    assert!(out.contains("█"));
+    // The axis is labelled from the phase timestamps: 23:15 + 9 × 5 min is midnight.
+    assert!(out.contains("         00          01          02"));
+    assert!(out.contains("Deep"));
+    assert!(out.contains("Awake"));
+}
+
+#[test]
+fn uncached_reads_leave_out_the_extra_day_requested() {
+    // The API is asked for one day more than needed; its night must not win.
+    let server = MockServer::start(|req| {
+        let reply = common::fixture_reply(req);
+        if !req.path.ends_with("/sleep") {
+            return reply;
+        }
+        let mut body: serde_json::Value = serde_json::from_str(&reply.body).unwrap();
+        let mut next = body["data"][0].clone();
+        next["day"] = "2024-02-14".into();
+        next["bedtime_start"] = "2024-02-13T22:00:00+08:00".into();
+        body["data"].as_array_mut().unwrap().insert(0, next);
+        Reply::json(body.to_string())
+    });
+    for extra in [&[][..], &["--no-cache"]] {
+        let out = stdout(
+            &Env::new()
+                .command(Some(&server))
+                .args(["hypnogram", DAY])
+                .args(extra)
+                .output()
+                .unwrap(),
+        );
+        assert!(out.contains("23:15 -> 07:05"), "{extra:?}");
+    }
+}
+
+#[test]
+fn hypnogram_rows_are_timestamped() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["hypnogram", DAY, "--format", "csv"]));
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines[0], "time,phase");
+    assert_eq!(lines[1], "2024-02-12T23:15:00+08:00,awake");
+    assert_eq!(lines[3], "2024-02-12T23:25:00+08:00,light");
+    assert_eq!(lines[7], "2024-02-12T23:45:00+08:00,deep");
+    assert_eq!(lines.len(), 71);
+}
+
+#[test]
+fn readiness_lists_contributors() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["readiness", DAY]));
+    assert!(out.contains("Readiness Score:"));
+    assert!(out.contains("Temp Deviation:  +0.6°C"));
+    assert!(out.contains("Resting Heart Rate"));
+
+    // App order, with contributors this version doesn't know about last
+    let position = |name: &str| out.find(name).unwrap();
+    assert!(position("Resting Heart Rate") < position("HRV Balance"));
+    assert!(position("HRV Balance") < position("Body Temperature"));
+    assert!(position("Previous Day Activity") < position("Activity Balance"));
+    assert!(position("Activity Balance") < position("Sleep Regularity"));
+}
+
+#[test]
+fn readiness_csv_has_stable_contributor_columns() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(
+        &server,
+        &[
+            "readiness",
+            "--from",
+            "2024-02-12",
+            "--to",
+            DAY,
+            "--format",
+            "csv",
+        ],
+    ));
+    let lines: Vec<_> = out.lines().collect();
+    let header: Vec<_> = lines[0].split(',').collect();
+    assert_eq!(header[4], "contributors_resting_heart_rate");
+    assert_eq!(header.last(), Some(&"contributors_activity_balance"));
+    assert!(!header.contains(&"contributors_sleep_regularity"));
+    // A day without data still fills every column
+    assert_eq!(lines[1].split(',').count(), header.len());
+    assert!(lines[2].starts_with("2024-02-13,79,0.6,0.1,92,75,100"));
+}
+
+#[test]
+fn activity_shows_steps_and_calories() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["activity", DAY]));
+    assert!(out.contains("Steps:          10,432"));
+    assert!(out.contains("Calories:       2,650 (active: 512)"));
+    assert!(out.contains("Walking Dist:   8.7 km"));
+}
+
+#[test]
+fn hrv_reads_from_sleep() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["hrv", DAY]));
+    assert!(out.contains("Avg HRV:     45 ms"));
+    assert!(out.contains("Avg Breath:  14.5 rpm"));
+}
+
+#[test]
+fn stress_shows_summary() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["stress", DAY]));
+    assert!(out.contains("restored"));
+    assert!(out.contains("Stress High:    1h 00m"));
+    assert!(out.contains("Recovery High:  2h 00m"));
+}
+
+#[test]
+fn json_prints_raw_payload() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["json", "daily_stress", DAY]));
+    let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(value["data"][0]["day_summary"], "restored");
+}
+
+#[test]
+fn trend_follows_pagination() {
+    let today = Local::now().date_naive();
+    let yesterday = today.checked_sub_days(Days::new(1)).unwrap();
+    let server = MockServer::start(move |req| {
+        if !req.path.ends_with("/daily_sleep") {
+            return Reply::json(r#"{"data": [], "next_token": null}"#);
+        }
+        match req.param("next_token") {
+            None => Reply::json(format!(
+                r#"{{"data": [{{"day": "{yesterday}", "score": 60}}], "next_token": "page-2"}}"#
+            )),
+            Some("page-2") => Reply::json(format!(
+                r#"{{"data": [{{"day": "{today}", "score": 90}}], "next_token": null}}"#
+            )),
+            Some(other) => Reply::status(400, format!("unexpected token {other}")),
+        }
+    });
+
+    let out = stdout(&run(&server, &["trend", "--days", "3"]));
+    assert!(out.contains("60"));
+    assert!(out.contains("90"));
+    // Average of both pages
+    assert!(out.contains("75"));
+    assert_eq!(server.requests_to("daily_sleep").len(), 2);
+
+    let capped = MockServer::start(move |req| {
+        Reply::json(format!(
+            r#"{{"data": [], "next_token": "more-{}"}}"#,
+            req.param("next_token").unwrap_or("0")
+        ))
+    });
+    let output = run(&capped, &["trend", "--max-pages", "2"]);
+    stdout(&output);
+    assert_eq!(capped.requests_to("daily_sleep").len(), 2);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("stopped after 2 page(s)"));
+}
+
+#[test]
+fn base_url_from_config_file() {
+    let server = MockServer::fixtures();
+    let env = Env::new();
+    env.write_config(&format!("base_url = \"{}\"\n", server.url));
+
+    let out = stdout(&env.command(None).args(["stress", DAY]).output().unwrap());
+    assert!(out.contains("restored"));
+    assert_eq!(server.requests_to("daily_stress").len(), 1);
+}
+
+#[test]
+fn env_base_url_overrides_config_file() {
+    let server = MockServer::fixtures();
+    let env = Env::new();
+    env.write_config("base_url = \"http://127.0.0.1:9\"\n");
+
+    let out = stdout(
+        &env.command(Some(&server))
+            .args(["stress", DAY])
+            .output()
+            .unwrap(),
+    );
+    assert!(out.contains("restored"));
+}
+
+#[test]
+fn api_errors_report_status() {
+    let server = MockServer::start(|_| Reply::status(401, "token expired"));
+    let output = run(&server, &["stress", DAY]);
+    assert!(!output.status.success());
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("401"));
+    assert!(stderr.contains("token expired"));
+    assert!(stderr.contains("regenerate it"));
+}
+
+#[test]
+fn error_classes_map_to_exit_codes() {
+    let server = MockServer::start(|_| Reply::status(404, "no such endpoint"));
+    let output = run(&server, &["json", "nope", DAY]);
+    assert_eq!(output.status.code(), Some(6));
+
+    let output = run(&server, &["stress", "2024-13-45"]);
+    assert_eq!(output.status.code(), Some(11));
+    assert!(server.requests_to("daily_stress").is_empty());
+
+    let output = Env::new()
+        .command(Some(&server))
+        .env_remove("OURA_TOKEN")
+        .arg("stress")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn retries_rate_limits_and_server_errors() {
+    let calls = AtomicUsize::new(0);
+    let server = MockServer::start(move |req| match calls.fetch_add(1, Ordering::SeqCst) {
+        0 => Reply::status(429, "slow down").header("Retry-After", "0"),
+        1 => Reply::status(503, "unavailable"),
+        _ => common::fixture_reply(req),
+    });
+
+    let out = stdout(&run(&server, &["stress", DAY, "--max-retry-delay", "0"]));
+    assert!(out.contains("restored"));
+    assert_eq!(server.requests_to("daily_stress").len(), 3);
+}
+
+#[test]
+fn gives_up_after_max_retries() {
+    let server = MockServer::start(|_| Reply::status(500, "boom").header("Retry-After", "0"));
+    let output = run(&server, &["stress", DAY, "--retries", "2"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(server.requests_to("daily_stress").len(), 3);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("500"));
+    assert!(stderr.contains("boom"));
+}
+
+#[test]
+fn settled_days_are_served_from_cache() {
+    let server = MockServer::fixtures();
+    let env = Env::new();
+
+    let first = stdout(
+        &env.command(Some(&server))
+            .args(["scores", DAY])
+            .output()
+            .unwrap(),
+    );
+    assert_eq!(server.requests().len(), 3);
+
+    let second = stdout(
+        &env.command(Some(&server))
+            .args(["scores", DAY])
+            .output()
+            .unwrap(),
+    );
+    assert_eq!(first, second);
+    assert_eq!(
+        server.requests().len(),
+        3,
+        "second run should not hit the API"
+    );
+
+    stdout(
+        &env.command(Some(&server))
+            .args(["scores", DAY, "--no-cache"])
+            .output()
+            .unwrap(),
+    );
+    assert_eq!(server.requests().len(), 6);
+}
+
+#[test]
+fn cache_is_kept_apart_per_token_and_host() {
+    let server = MockServer::fixtures();
+    let env = Env::new();
+    let stress = |server: &MockServer, extra: &[&str]| {
+        let output = env
+            .command(Some(server))
+            .args(["stress", DAY])
+            .args(extra)
+            .output()
+            .unwrap();
+        stdout(&output);
+    };
+
+    stress(&server, &[]);
+    stress(&server, &[]);
+    assert_eq!(server.requests_to("daily_stress").len(), 1);
+
+    // Someone else's token doesn't see the days fetched with this one.
+    stress(&server, &["--token", "other-token"]);
+    assert_eq!(server.requests_to("daily_stress").len(), 2);
+
+    // Nor does another API host.
+    let other = MockServer::fixtures();
+    stress(&other, &[]);
+    assert_eq!(other.requests_to("daily_stress").len(), 1);
+}
+
+#[test]
+fn recent_days_are_refetched() {
+    let server = MockServer::fixtures();
+    let env = Env::new();
+    for _ in 0..2 {
+        stdout(&env.command(Some(&server)).arg("stress").output().unwrap());
+    }
+    assert_eq!(server.requests_to("daily_stress").len(), 2);
+}
+
+#[test]
+fn offline_reads_cache_without_network() {
+    let server = MockServer::fixtures();
+    let env = Env::new();
+    stdout(
+        &env.command(Some(&server))
+            .args(["stress", DAY])
+            .output()
+            .unwrap(),
+    );
+
+    let fetched = server.requests().len();
+    let out = stdout(
+        &env.command(Some(&server))
+            .args(["stress", DAY, "--offline"])
+            .output()
+            .unwrap(),
+    );
+    assert!(out.contains("restored"));
+
+    let json = stdout(
+        &env.command(Some(&server))
+            .args(["json", "daily_stress", DAY, "--offline"])
+            .output()
+            .unwrap(),
+    );
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["data"][0]["day_summary"], "restored");
+
+    let out = stdout(
+        &env.command(Some(&server))
+            .args(["stress", "2024-02-01", "--offline"])
+            .output()
+            .unwrap(),
+    );
+    assert!(out.contains("No stress data"));
+    assert_eq!(server.requests().len(), fetched);
+
+    let output = env
+        .command(Some(&server))
+        .args([
+            "stress",
+            "--from",
+            "2024-02-01",
+            "--to",
+            "2024-02-03",
+            "--offline",
+        ])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("daily_stress isn't cached for 3 days, 2024-02-01 → 2024-02-03"));
+}
+
+#[test]
+fn sync_archives_history_and_resumes() {
+    let today = Local::now().date_naive();
+    let since = today.checked_sub_days(Days::new(20)).unwrap();
+    let server = MockServer::fixtures();
+    let env = Env::new();
+
+    let since_arg = since.to_string();
+    let args = [
+        "sync",
+        "--since",
+        since_arg.as_str(),
+        "--endpoint",
+        "daily_stress",
+        "--endpoint",
+        "heartrate",
+    ];
+    let out = stdout(&env.command(Some(&server)).args(args).output().unwrap());
+    assert!(out.contains("Syncing account"));
+    assert!(out.contains("sam@example.com"));
+    assert!(out.contains("daily_stress"));
+
+    // The archive lives with user data, not in a directory cleaners may wipe.
+    let archives = std::fs::read_dir(env.home.path().join("data/oura")).unwrap();
+    assert_eq!(archives.count(), 1);
+    assert!(!env.home.path().join("cache/oura").exists());
+
+    let stress = server.requests_to("daily_stress");
+    assert_eq!(stress.len(), 1);
+    assert_eq!(stress[0].param("start_date"), Some(since_arg.as_str()));
+
+    // 21 days in 7-day chunks, requested by datetime
+    let heartrate = server.requests_to("heartrate");
+    assert_eq!(heartrate.len(), 3);
+    assert_eq!(
+        heartrate[0].param("start_datetime"),
+        Some(format!("{since}T00:00:00+00:00").as_str())
+    );
+
+    // A second sync only re-fetches the unsettled recent days.
+    stdout(&env.command(Some(&server)).args(args).output().unwrap());
+    let stress = server.requests_to("daily_stress");
+    assert_eq!(stress.len(), 2);
+    let recent = today.checked_sub_days(Days::new(2)).unwrap().to_string();
+    assert_eq!(stress[1].param("start_date"), Some(recent.as_str()));
+
+    // Unknown endpoint names are rejected rather than silently matching nothing.
+    let output = env
+        .command(Some(&server))
+        .args(["sync", "--endpoint", "daily_stres"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("daily_stress"));
+}
+
+#[test]
+fn interrupted_sync_resumes_from_last_chunk() {
+    use std::sync::Arc;
+
+    let env = Env::new();
+    let recovered = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let recovered = Arc::clone(&recovered);
+        MockServer::start(move |req| {
+            let down = recovered.load(Ordering::SeqCst) == 0;
+            if down && req.param("start_date").is_some_and(|d| d > "2024-03-01") {
+                Reply::status(500, "interrupted")
+            } else {
+                common::fixture_reply(req)
+            }
+        })
+    };
+    let args = [
+        "sync",
+        "--since",
+        "2024-01-01",
+        "--endpoint",
+        "daily_stress",
+        "--retries",
+        "0",
+    ];
+    let output = env.command(Some(&server)).args(args).output().unwrap();
+    assert!(!output.status.success());
+    assert_eq!(server.requests_to("daily_stress").len(), 2);
+
+    recovered.store(1, Ordering::SeqCst);
+    stdout(
+        &env.command(Some(&server))
+            .args(&args[..5])
+            .output()
+            .unwrap(),
+    );
+    let requests = server.requests_to("daily_stress");
+    assert_eq!(requests[2].param("start_date"), Some("2024-03-31"));
+
+    // Archived days are readable offline.
+    let out = stdout(
+        &env.command(Some(&server))
+            .args(["stress", DAY, "--offline"])
+            .output()
+            .unwrap(),
+    );
+    assert!(out.contains("restored"));
+}
+
+#[test]
+fn per_day_commands_accept_ranges() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(
+        &server,
+        &["stress", "--from", "2024-02-12", "--to", DAY],
+    ));
+    assert!(out.contains("Mon Feb 12"));
+    assert!(out.contains("No stress data"));
+    assert!(out.contains("Tue Feb 13"));
+    assert!(out.contains("restored"));
+
+    let requests = server.requests_to("daily_stress");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].param("start_date"), Some("2024-02-12"));
+    assert_eq!(requests[0].param("end_date"), Some("2024-02-14"));
+
+    let out = stdout(&run(&server, &["sleep", "--last", "3d", "--to", DAY]));
+    assert!(out.contains("Sun Feb 11"));
+    assert!(out.contains("Total Sleep: 7h 00m"));
+
+    let out = stdout(&run(
+        &server,
+        &["json", "daily_stress", "--last", "1w", "--to", DAY],
+    ));
+    let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(value["data"].as_array().unwrap().len(), 1);
+    let requests = server.requests_to("daily_stress");
+    assert_eq!(
+        requests.last().unwrap().param("start_date"),
+        Some("2024-02-07")
+    );
+}
+
+#[test]
+fn trend_accepts_end_date() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["trend", "--days", "3", "--end", DAY]));
+    assert!(out.contains("Sun Feb 11"));
+    assert!(out.contains("Tue Feb 13"));
+    let requests = server.requests_to("daily_sleep");
+    assert_eq!(requests[0].param("start_date"), Some("2024-02-11"));
+
+    let output = run(&server, &["trend", "--days", "4000000000", "--end", DAY]);
+    assert_eq!(output.status.code(), Some(11));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid span: 4000000000 days ending 2024-02-13"));
+}
+
+#[test]
+fn date_flags_accept_days_ago() {
+    // The binary runs with TZ=UTC.
+    let today = chrono::Utc::now().date_naive();
+    let ago = |n| today.checked_sub_days(Days::new(n)).unwrap().to_string();
+    let server = MockServer::fixtures();
+    let env = Env::new();
+    let run = |args: &[&str]| stdout(&env.command(Some(&server)).args(args).output().unwrap());
+
+    run(&["trend", "--days", "2", "--end", "-3", "--no-cache"]);
+    let request = &server.requests_to("daily_sleep")[0];
+    assert_eq!(request.param("start_date"), Some(ago(4).as_str()));
+
+    run(&["stress", "--from", "-2", "--to", "-1", "--no-cache"]);
+    let request = &server.requests_to("daily_stress")[0];
+    assert_eq!(request.param("start_date"), Some(ago(2).as_str()));
+
+    run(&[
+        "sync",
+        "--since",
+        "-3",
+        "--endpoint",
+        "daily_resilience",
+        "--yes",
+    ]);
+    let request = &server.requests_to("daily_resilience")[0];
+    assert_eq!(request.param("start_date"), Some(ago(3).as_str()));
+}
+
+#[test]
+fn natural_language_dates() {
+    use chrono::{Datelike, Weekday};
+
+    let today = Local::now().date_naive();
+    let ago = |n: u64| today.checked_sub_days(Days::new(n)).unwrap();
+    let last_monday = {
+        let back = (today.weekday().num_days_from_monday() + 6) % 7 + 1;
+        ago(back as u64)
+    };
+    let this_week_start = ago(today.weekday().num_days_from_monday() as u64);
+    let cases = [
+        ("3 days ago", ago(3), ago(3)),
+        ("-2", ago(2), ago(2)),
+        ("last monday", last_monday, last_monday),
+        (
+            "last week",
+            this_week_start - Days::new(7),
+            this_week_start - Days::new(1),
+        ),
+        ("this month", today.with_day(1).unwrap(), today),
+        (
+            "2024-W07",
+            chrono::NaiveDate::from_isoywd_opt(2024, 7, Weekday::Mon).unwrap(),
+            chrono::NaiveDate::from_isoywd_opt(2024, 7, Weekday::Sun).unwrap(),
+        ),
+    ];
+
+    for (spec, start, end) in cases {
+        let server = MockServer::fixtures();
+        stdout(&run(&server, &["json", "daily_stress", spec, "--no-cache"]));
+        let request = &server.requests_to("daily_stress")[0];
+        let end_plus = end.succ_opt().unwrap().to_string();
+        assert_eq!(
+            request.param("start_date"),
+            Some(start.to_string().as_str()),
+            "{spec}"
+        );
+        assert_eq!(request.param("end_date"), Some(end_plus.as_str()), "{spec}");
+    }
+
+    // A period renders one block per day.
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["stress", "2024-W07"]));
+    assert!(out.contains("Mon Feb 12"));
+    assert!(out.contains("Sun Feb 18"));
+
+    let output = run(&server, &["stress", "someday"]);
+    assert_eq!(output.status.code(), Some(11));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("3 days ago"));
+
+    // Spans reaching before the earliest representable day are errors, not panics.
+    // Spans and windows that don't fit share one error and exit code.
+    for span in ["9999999999999d", "9999999999999999999w", "fortnight"] {
+        let output = run(&server, &["stress", "--last", span]);
+        assert_eq!(output.status.code(), Some(11), "{span}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("Invalid span"), "{stderr}");
+        assert!(!stderr.contains("YYYY-MM-DD"), "{stderr}");
+    }
+    let output = run(&server, &["stress", "9999999999999999999 weeks ago"]);
+    assert_eq!(output.status.code(), Some(11));
+
+    let output = run(&server, &["analyze", "last week"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("single day"));
+}
+
+#[test]
+fn scores_as_csv() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["scores", DAY, "--format", "csv"]));
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "date,sleep_score,readiness_score,activity_score,temperature_deviation",
+            "2024-02-13,82,79,91,0.6",
+        ]
+    );
+}
+
+#[test]
+fn trend_as_tsv_ends_with_average() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(
+        &server,
+        &["--format", "tsv", "trend", "--days", "2", "--end", DAY],
+    ));
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(
+        lines[0],
+        "date\tsleep\treadiness\tactivity\tspo2\tbdi\trest_mode"
+    );
+    assert_eq!(lines[1], "2024-02-12\t\t\t\t\t\ttrue");
+    assert_eq!(lines[2], "2024-02-13\t82\t79\t91\t93.8\t18\tfalse");
+    assert_eq!(lines[3], "average\t82\t79\t91\t93.8\t18\tfalse");
+}
+
+#[test]
+fn analyze_as_json() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["analyze", DAY, "--format", "json"]));
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["day"], DAY);
+    assert_eq!(json["sleep_score"], 82);
+    let kinds: Vec<_> = json["flags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["kind"].as_str().unwrap())
+        .collect();
+    assert!(kinds.contains(&"low_deep_sleep"));
+    assert!(kinds.contains(&"high_restlessness"));
+    let deep = json["flags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["kind"] == "low_deep_sleep")
+        .unwrap();
+    assert_eq!(deep["severity"], "issue");
+    assert_eq!(deep["value"], 3000.0);
+}
+
+#[test]
+fn range_as_json_has_one_entry_per_day() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(
+        &server,
+        &[
+            "readiness",
+            "--from",
+            "2024-02-12",
+            "--to",
+            DAY,
+            "--format",
+            "json",
+        ],
+    ));
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    let days = json.as_array().unwrap();
+    assert_eq!(days.len(), 2);
+    assert_eq!(days[0]["date"], "2024-02-12");
+    assert_eq!(days[0]["score"], serde_json::Value::Null);
+    assert_eq!(days[1]["score"], 79);
+}
+
+#[test]
+fn raw_json_as_csv_flattens_records() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(
+        &server,
+        &["json", "daily_sleep", DAY, "--format", "csv"],
+    ));
+    let mut lines = out.lines();
+    let header: Vec<_> = lines.next().unwrap().split(',').collect();
+    assert!(header.contains(&"contributors_deep_sleep"));
+    assert_eq!(lines.count(), 1);
+}
+
+#[test]
+fn heartrate_summarizes_sources() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["heartrate", DAY]));
+    let lines: Vec<_> = out.lines().map(str::trim_end).collect();
+    assert!(lines.contains(&"  awake         70    74    79       28"));
+    assert!(lines.contains(&"  workout      120   125   130        2"));
+    assert!(out.contains('█'));
+    assert!(out.contains("52–130 bpm"));
+
+    let requests = server.requests_to("heartrate");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].param("start_datetime"),
+        Some("2024-02-13T00:00:00+00:00")
+    );
+    assert_eq!(
+        requests[0].param("end_datetime"),
+        Some("2024-02-14T00:00:00+00:00")
+    );
+}
+
+#[test]
+fn heartrate_days_follow_local_time() {
+    let server = MockServer::fixtures();
+    let output = Env::new()
+        .command(Some(&server))
+        .env("TZ", "Europe/Berlin")
+        .args(["heartrate", DAY, "--format", "json"])
+        .output()
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    // 23:00 and 23:30 UTC are already the next day in Berlin.
+    assert_eq!(json[0]["samples"].as_array().unwrap().len(), 46);
+
+    let request = &server.requests_to("heartrate")[0];
+    assert_eq!(
+        request.param("start_datetime"),
+        Some("2024-02-13T00:00:00+01:00")
+    );
+    assert_eq!(
+        request.param("end_datetime"),
+        Some("2024-02-14T00:00:00+01:00")
+    );
+}
+
+#[test]
+fn empty_csv_results_keep_their_header() {
+    let server = MockServer::start(|_| Reply::json(r#"{"data": [], "next_token": null}"#));
+    let out = stdout(&run(&server, &["workouts", DAY, "--format", "csv"]));
+    assert_eq!(
+        out,
+        "date,start,end,activity,label,intensity,duration,calories,distance,source\n"
+    );
+    let out = stdout(&run(&server, &["hypnogram", DAY, "--format", "tsv"]));
+    assert_eq!(out, "time\tphase\n");
+}
+
+#[test]
+fn heartrate_as_csv_lists_samples() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["heartrate", DAY, "--format", "csv"]));
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines[0], "timestamp,bpm,source");
+    assert_eq!(lines[1], "2024-02-13T00:00:00+00:00,58,sleep");
+    assert_eq!(lines.len(), 49);
+}
+
+#[test]
+fn workouts_list_with_totals() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(
+        &server,
+        &["workouts", "--from", "2024-02-12", "--to", DAY],
+    ));
+    let lines: Vec<_> = out.lines().map(str::trim_end).collect();
+    let running = lines.iter().position(|l| l.contains("hard")).unwrap();
+    let walking = lines.iter().position(|l| l.contains("easy")).unwrap();
+    assert!(walking < running, "oldest first");
+    assert!(lines[running].contains("45m"));
+    assert!(lines[running].contains("7.2 km"));
+    assert!(lines[running].contains("autodetected"));
+    assert!(lines.contains(&"  Running               2 ×   1h 15m  712 kcal   12.2 km"));
+    assert!(lines.contains(&"  Strength Training     1 ×   1h 00m  180 kcal"));
+
+    let requests = server.requests_to("workout");
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].param("start_date"), Some("2024-02-12"));
+}
+
+#[test]
+fn workouts_as_json() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["workouts", DAY, "--format", "json"]));
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["workouts"].as_array().unwrap().len(), 4);
+    assert_eq!(json["workouts"][0]["activity"], "walking");
+    assert_eq!(json["workouts"][0]["duration"], 1800);
+    assert_eq!(json["totals"][0]["activity"], "running");
+    assert_eq!(json["totals"][0]["count"], 2);
+    assert_eq!(json["totals"][0]["duration"], 4500);
+}
+
+#[test]
+fn sessions_list_and_chart() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["sessions", DAY]));
+    assert!(out.contains("Meditation"));
+    assert!(out.contains("Breathing"));
+    assert!(out.contains("10m"));
+    assert!(out.contains("good"));
+    // Two sessions: no charts unless asked.
+    assert!(!out.contains("bpm"));
+
+    let out = stdout(&run(&server, &["sessions", DAY, "--detail"]));
+    assert!(out.contains("Meditation"));
+    assert!(out.contains("07:30 -> 07:40"));
+    assert!(out.contains("68 → 62 bpm"));
+    assert!(out.contains("39 → 53 ms"));
+    assert!(out.contains("(no heart rate recorded)"));
+}
+
+#[test]
+fn sessions_as_csv() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["sessions", DAY, "--format", "csv"]));
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(
+        lines[0],
+        "date,start,end,type,duration,mood,heart_rate_before,heart_rate_after,hrv_before,hrv_after"
+    );
+    assert!(lines[1].contains(",meditation,600,good,"));
+    assert!(lines[2].ends_with(",breathing,300,,,,,"));
+}
+
+#[test]
+fn tags_merge_both_formats() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(
+        &server,
+        &["tags", "--from", "2024-02-12", "--to", DAY],
+    ));
+    let lines: Vec<_> = out.lines().collect();
+    assert!(lines[1].contains("15:00") && lines[1].contains("caffeine"));
+    assert!(lines[2].contains("21:40") && lines[2].contains("alcohol"));
+    assert!(lines[2].contains("two glasses of wine"));
+    assert!(lines[3].contains("12:30–13:15") && lines[3].contains("Sauna"));
+}
+
+#[test]
+fn analyze_notes_tags_from_the_evening_before() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["analyze", DAY]));
+    let deep = out.lines().find(|l| l.contains("Low deep sleep")).unwrap();
+    assert!(deep.contains("alcohol tagged at 21:40"));
+    assert!(deep.contains("caffeine tagged at 15:00"));
+    // After waking up, so not a factor.
+    assert!(!out.contains("Sauna"));
+    let wake = out.lines().find(|l| l.contains("Woke at")).unwrap();
+    assert!(!wake.contains("tagged"));
+
+    for endpoint in ["tag", "enhanced_tag"] {
+        let requests = server.requests_to(endpoint);
+        assert_eq!(requests[0].param("start_date"), Some("2024-02-12"));
+    }
+
+    let out = stdout(&run(&server, &["analyze", DAY, "--format", "json"]));
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["tags"].as_array().unwrap().len(), 2);
+    assert_eq!(json["tags"][1]["name"], "alcohol");
+}
+
+#[test]
+fn spo2_shows_average_and_breathing_index() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["spo2", DAY]));
+    assert!(out.contains("93.8%"));
+    assert!(out.contains("18/h"));
+}
+
+#[test]
+fn profile_overrides_thresholds_and_format() {
+    let server = MockServer::fixtures();
+    let env = Env::new();
+    env.write_config(
+        r#"
+[thresholds]
+min_deep_sleep_minutes = 45
+
+[profiles.sprinter]
+format = "json"
+
+[profiles.sprinter.thresholds]
+max_restless_periods = 30
+"#,
+    );
+
+    let out = stdout(
+        &env.command(Some(&server))
+            .args(["analyze", DAY])
+            .output()
+            .unwrap(),
+    );
+    assert!(out.contains("High restlessness: 24 periods"));
+    assert!(!out.contains("Low deep sleep"));
+
+    let out = stdout(
+        &env.command(Some(&server))
+            .args(["--profile", "sprinter", "analyze", DAY])
+            .output()
+            .unwrap(),
+    );
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    let kinds: Vec<_> = json["flags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["kind"].as_str().unwrap())
+        .collect();
+    assert!(!kinds.contains(&"high_restlessness"));
+    // The top-level threshold still applies: 50m deep sleep is above 45m
+    assert!(!kinds.contains(&"low_deep_sleep"));
+    assert!(kinds.contains(&"temp_elevated"));
+
+    // An explicit --format beats the profile's
+    let out = stdout(
+        &env.command(Some(&server))
+            .args(["--profile", "sprinter", "--format", "text", "stress", DAY])
+            .output()
+            .unwrap(),
+    );
+    assert!(out.contains("restored"));
+
+    let output = env
+        .command(Some(&server))
+        .args(["--profile", "nobody", "stress", DAY])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("available: sprinter"));
+}
+
+#[test]
+fn token_from_profile_in_explicit_config_file() {
+    let server = MockServer::fixtures();
+    let env = Env::new();
+    let path = env.home.path().join("team.toml");
+    std::fs::write(
+        &path,
+        "default_profile = \"alex\"\n\n[profiles.alex]\ntoken_env = \"ALEX_OURA_TOKEN\"\n\n[profiles.sam]\ntoken = \"sam-token\"\n",
+    )
+    .unwrap();
+
+    let oura = |args: &[&str]| {
+        env.command(Some(&server))
+            .env_remove("OURA_TOKEN")
+            .env("ALEX_OURA_TOKEN", "alex-token")
+            .arg("--config")
+            .arg(&path)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    stdout(&oura(&["stress", DAY]));
+    stdout(&oura(&["--profile", "sam", "stress", DAY]));
+    let auth: Vec<_> = server
+        .requests_to("daily_stress")
+        .iter()
+        .map(|r| r.header("authorization").unwrap().to_string())
+        .collect();
+    // Each profile has its own cache, so sam's request isn't served alex's data
+    assert_eq!(auth, ["Bearer alex-token", "Bearer sam-token"]);
+
+    let missing = env
+        .command(Some(&server))
+        .args(["--config", "/nonexistent/oura.toml", "stress", DAY])
+        .output()
+        .unwrap();
+    assert!(!missing.status.success());
+}
+
+#[test]
+fn scores_are_colored_by_configured_bands() {
+    let server = MockServer::fixtures();
+    let red = "\u{1b}[31m82";
+    let out = stdout(&run(&server, &["hypnogram", DAY]));
+    assert!(!out.contains(red));
+
+    let env = Env::new();
+    env.write_config("[thresholds]\ngood_score = 95\nfair_score = 90\n");
+    for args in [&["hypnogram", DAY][..], &["trend", "--days", "1", "--end", DAY]] {
+        let out = stdout(&env.command(Some(&server)).args(args).output().unwrap());
+        assert!(out.contains(red), "{args:?}: {out}");
+    }
+}
+
+#[test]
+fn analyze_flags_spo2_against_configured_thresholds() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["analyze", DAY]));
+    assert!(out.contains("Low SpO2: 93.8%"));
+    assert!(out.contains("Breathing disturbances: 18/h"));
+
+    let env = Env::new();
+    env.write_config("[thresholds]\nmin_spo2 = 93.0\nmax_bdi = 20\nbaseline_delta = 10\n");
+    let out = stdout(
+        &env.command(Some(&server))
+            .args(["analyze", DAY])
+            .output()
+            .unwrap(),
+    );
+    assert!(!out.contains("SpO2"));
+    assert!(!out.contains("Breathing disturbances"));
+    assert!(!out.contains("baseline"));
+    assert!(out.contains("Low deep sleep"));
+}
+
+#[test]
+fn fitness_combines_latest_readings() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["fitness", DAY, "--window", "30"]));
+    assert!(out.contains("Vascular Age:  38"));
+    assert!(out.contains("(as of Feb 11)"));
+    assert!(out.contains("solid"));
+    assert!(out.contains("Sleep Recovery"));
+    assert!(out.contains("Daytime Recovery"));
+    assert!(out.contains("42.0 ml/kg/min"));
+    assert!(out.contains("+1.5 over 30 days"));
+
+    for endpoint in ["daily_cardiovascular_age", "daily_resilience", "vO2_max"] {
+        let requests = server.requests_to(endpoint);
+        assert_eq!(requests.len(), 1, "{endpoint}");
+        assert_eq!(requests[0].param("start_date"), Some("2024-01-15"));
+        assert_eq!(requests[0].param("end_date"), Some("2024-02-14"));
+    }
+
+    let output = run(&server, &["fitness", DAY, "--window", "4000000000"]);
+    assert_eq!(output.status.code(), Some(11));
+}
+
+#[test]
+fn fitness_as_json() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["fitness", DAY, "--format", "json"]));
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["vascular_age"]["value"], 38);
+    assert_eq!(json["resilience"]["level"], "solid");
+    assert_eq!(json["vo2_max"]["latest"]["value"], 42.0);
+    assert_eq!(json["vo2_max"]["history"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn trend_marks_rest_mode_days() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["trend", "--days", "3", "--end", DAY]));
+    let marked: Vec<_> = out.lines().filter(|l| l.contains("rest mode")).collect();
+    assert_eq!(marked.len(), 1);
+    assert!(marked[0].contains("Mon Feb 12"));
+
+    // Looks back for periods that started before the trend window
+    let request = &server.requests_to("rest_mode_period")[0];
+    assert_eq!(request.param("start_date"), Some("2023-12-13"));
+}
+
+#[test]
+fn cached_open_rest_mode_period_is_refetched_once_it_ends() {
+    use std::sync::Arc;
+
+    let ended = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let ended = Arc::clone(&ended);
+        MockServer::start(move |req| {
+            if !req.path.ends_with("/rest_mode_period") {
+                return common::fixture_reply(req);
+            }
+            let end_day = match ended.load(Ordering::SeqCst) {
+                0 => "null",
+                _ => r#""2024-02-12""#,
+            };
+            Reply::json(format!(
+                r#"{{"data": [{{"start_day": "2024-02-12", "end_day": {end_day}}}], "next_token": null}}"#
+            ))
+        })
+    };
+    let env = Env::new();
+    let args = ["trend", "--days", "3", "--end", DAY];
+    let rest_days = || {
+        let out = stdout(&env.command(Some(&server)).args(args).output().unwrap());
+        out.lines().filter(|l| l.contains("rest mode")).count()
+    };
+
+    assert_eq!(rest_days(), 2);
+    ended.store(1, Ordering::SeqCst);
+    assert_eq!(rest_days(), 1);
+    assert_eq!(server.requests_to("rest_mode_period").len(), 2);
+}
+
+#[test]
+fn bedtime_compares_nights_to_window() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["bedtime", DAY]));
+    assert!(out.contains("22:30 – 22:55"));
+    assert!(out.contains("Try going to bed earlier"));
+    assert!(out.contains("Tue Feb 13"));
+    assert!(out.contains("23:15"));
+    assert!(out.contains("20m late"));
+
+    let request = &server.requests_to("sleep_time")[0];
+    assert_eq!(request.param("start_date"), Some("2024-02-07"));
+
+    let output = run(&server, &["bedtime", DAY, "--days", "4000000000"]);
+    assert_eq!(output.status.code(), Some(11));
+}
+
+#[test]
+fn bedtime_as_json() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["bedtime", DAY, "--format", "json"]));
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(json["window_start"], "22:30");
+    assert_eq!(json["status"], "optimal_found");
+    assert_eq!(json["nights"][0]["minutes_off"], 20);
+}
+
+#[test]
+fn whoami_shows_account() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["whoami"]));
+    assert!(out.contains("Email:   sam@example.com"));
+    assert!(out.contains("Age:     34"));
+    assert!(out.contains("Height:  1.72 m"));
+    assert!(out.contains("Weight:  65.5 kg"));
+
+    let request = &server.requests_to("personal_info")[0];
+    assert_eq!(request.param("start_date"), None);
+}
+
+#[test]
+fn ring_lists_newest_first() {
+    let server = MockServer::fixtures();
+    let out = stdout(&run(&server, &["ring"]));
+    let gen4 = out.find("Gen4 · Horizon · Silver · size 9").unwrap();
+    let gen3 = out
+        .find("Gen3 · Heritage · Stealth Black · size 9")
+        .unwrap();
+    assert!(gen4 < gen3);
+    assert!(out.contains("Firmware:  1.4.0"));
+    assert!(out.contains("Set up:    2024-10-20"));
+}
+
+#[test]
+fn sync_checks_cache_before_contacting_account() {
+    let server = MockServer::fixtures();
+    let output = run(&server, &["sync", "--no-cache"]);
+    assert_eq!(output.status.code(), Some(15));
+    assert!(server.requests().is_empty());
+}
+
+#[test]
+fn auth_login_stores_encrypted_token() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let server = MockServer::start(|req| match req.header("authorization") {
+        Some("Bearer stored-token" | "Bearer env-token") => {
+            Reply::json(std::fs::read_to_string("tests/fixtures/personal_info.json").unwrap())
+        }
+        _ => Reply::status(401, "bad token"),
+    });
+    let env = Env::new();
+    let oura = |args: &[&str]| {
+        let mut cmd = env.command(Some(&server));
+        cmd.env_remove("OURA_TOKEN")
+            .env("OURA_PASSPHRASE", "correct horse")
+            .args(args);
+        cmd
+    };
+    let login = |token: &str, args: &[&str]| {
+        let mut child = oura(&[&["auth", "login"], args].concat())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        writeln!(child.stdin.take().unwrap(), "{token}").unwrap();
+        child.wait_with_output().unwrap()
+    };
+
+    // The keyring is off in tests, and a rejected token is never stored
+    assert!(!login("stored-token", &[]).status.success());
+    assert_eq!(login("wrong-token", &["--file"]).status.code(), Some(4));
+    assert_eq!(
+        oura(&["auth", "status"]).output().unwrap().status.code(),
+        Some(3)
+    );
+
+    let out = stdout(&login("stored-token", &["--file"]));
+    assert!(out.contains("Logged in as"));
+    assert!(out.contains("sam@example.com"));
+    let file = env.config_dir().join("oura").join("token.age");
+    let saved = std::fs::read(&file).unwrap();
+    assert!(!String::from_utf8_lossy(&saved).contains("stored-token"));
+
+    let out = stdout(&oura(&["auth", "status"]).output().unwrap());
+    assert!(out.contains("Source:  encrypted file"));
+    assert!(out.contains("Account: sam@example.com"));
+    assert!(out.contains("valid"));
+
+    let out = stdout(
+        &oura(&["auth", "status"])
+            .env("OURA_TOKEN", "env-token")
+            .output()
+            .unwrap(),
+    );
+    assert!(out.contains("OURA_TOKEN"));
+    let out = oura(&["--token", "flag-token", "auth", "status"])
+        .env("OURA_TOKEN", "env-token")
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("--token flag"));
+
+    let wrong = oura(&["auth", "status"])
+        .env("OURA_PASSPHRASE", "battery staple")
+        .output()
+        .unwrap();
+    assert!(!wrong.status.success());
+    assert!(String::from_utf8_lossy(&wrong.stderr).contains("wrong passphrase"));
+
+    let out = stdout(&oura(&["auth", "logout"]).output().unwrap());
+    assert!(out.contains("Removed the token from the encrypted file"));
+    assert!(!file.exists());
+    let out = stdout(&oura(&["auth", "logout"]).output().unwrap());
+    assert!(out.contains("No stored token"));
+}
+
+#[test]
+fn oauth_login_with_pkce_and_refresh_on_401() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::sync::{Arc, Mutex};
+
+    use base64::Engine;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use sha2::{Digest, Sha256};
+
+    // A stand-in authorization server. The code challenge is read off the
+    // authorize URL the CLI prints, as a real server would receive it.
+    let challenge = Arc::new(Mutex::new(String::new()));
+    let expected = Arc::clone(&challenge);
+    let server = MockServer::start(move |req| {
+        let form = common::parse_query(&req.body);
+        let field = |name: &str| {
+            form.iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+        };
+        let token = |access: &str, refresh: &str| {
+            Reply::json(format!(
+                r#"{{"access_token":"{access}","refresh_token":"{refresh}","expires_in":86400,"scope":"personal daily","token_type":"bearer"}}"#
+            ))
+        };
+        match (req.path.as_str(), req.header("authorization")) {
+            ("/oauth/token", _) => match field("grant_type") {
+                Some("authorization_code") => {
+                    let verifier = field("code_verifier").unwrap_or_default();
+                    let hashed = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+                    if field("code") == Some("auth-code")
+                        && field("client_id") == Some("test-client")
+                        && hashed == *expected.lock().unwrap()
+                    {
+                        token("access-1", "refresh-1")
+                    } else {
+                        Reply::status(400, r#"{"error":"invalid_grant"}"#)
+                    }
+                }
+                Some("refresh_token") if field("refresh_token") == Some("refresh-1") => {
+                    token("access-2", "refresh-2")
+                }
+                _ => Reply::status(400, r#"{"error":"invalid_grant"}"#),
+            },
+            ("/v2/usercollection/personal_info", Some("Bearer access-1" | "Bearer access-2")) => {
+                common::fixture_reply(req)
+            }
+            // The first access token has "expired" by the time data is read.
+            (_, Some("Bearer access-2")) => common::fixture_reply(req),
+            _ => Reply::status(401, "token expired"),
+        }
+    });
+    let env = Env::new();
+    env.write_config(&format!(
+        "[oauth]\nauthorize_url = \"{0}/oauth/authorize\"\ntoken_url = \"{0}/oauth/token\"\n\
+         redirect_uri = \"http://127.0.0.1:0/done\"\n",
+        server.url
+    ));
+    let oura = |args: &[&str]| {
+        let mut cmd = env.command(Some(&server));
+        cmd.env_remove("OURA_TOKEN")
+            .env("OURA_PASSPHRASE", "correct horse")
+            .args(args);
+        cmd
+    };
+
+    let mut child = oura(&[
+        "auth",
+        "login",
+        "--oauth",
+        "--file",
+        "--no-browser",
+        "--client-id",
+        "test-client",
+        "--scope",
+        "personal",
+        "--scope",
+        "daily",
+    ])
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let url = loop {
+        let mut line = String::new();
+        assert_ne!(stderr.read_line(&mut line).unwrap(), 0, "no authorize URL");
+        if let Some(url) = line
+            .trim()
+            .strip_prefix(&format!("{}/oauth/authorize?", server.url))
+        {
+            break common::parse_query(url);
+        }
+    };
+    let param = |name: &str| {
+        url.iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+            .unwrap()
+    };
+    assert_eq!(param("scope"), "personal daily");
+    assert_eq!(param("code_challenge_method"), "S256");
+    *challenge.lock().unwrap() = param("code_challenge");
+
+    let redirect = param("redirect_uri");
+    assert!(redirect.starts_with("http://127.0.0.1:") && redirect.ends_with("/done"));
+    // Retried until the listener is up.
+    let callback = |state: &str| loop {
+        match reqwest::blocking::get(format!("{redirect}?code=auth-code&state={state}")) {
+            Ok(resp) => break resp.text().unwrap(),
+            Err(_) => std::thread::sleep(std::time::Duration::from_millis(50)),
+        }
+    };
+    // A preconnect socket that never sends a request doesn't block the redirect.
+    let idle = std::net::TcpStream::connect(
+        redirect
+            .trim_start_matches("http://")
+            .split('/')
+            .next()
+            .unwrap(),
+    )
+    .unwrap();
+    assert!(callback(&param("state")).contains("Authorized"));
+    drop(idle);
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success());
+    let out = stdout(&out);
+    assert!(out.contains("sam@example.com"));
+    assert!(out.contains("OAuth tokens saved to the encrypted file"));
+
+    let out = stdout(&oura(&["auth", "status"]).output().unwrap());
+    assert!(out.contains("Type:    OAuth (personal daily)"));
+
+    // access-1 is rejected: the client refreshes once, retries and saves the
+    // rotated tokens, so the next run goes straight to the API.
+    let refreshes = || {
+        server
+            .requests()
+            .into_iter()
+            .filter(|r| r.path == "/oauth/token" && r.body.contains("grant_type=refresh_token"))
+            .count()
+    };
+    let out = oura(&["stress", DAY]).output().unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(refreshes(), 1);
+    let out = oura(&["--no-cache", "stress", DAY]).output().unwrap();
+    assert!(out.status.success());
+    assert_eq!(refreshes(), 1);
+    let last = server.requests_to("daily_stress").pop().unwrap();
+    assert_eq!(last.header("authorization"), Some("Bearer access-2"));
+}
+
+#[test]
+fn all_accounts_compares_scores_and_trend() {
+    let server = MockServer::start(|req| match req.header("authorization") {
+        Some("Bearer alice-token") => common::fixture_reply(req),
+        Some("Bearer bob-token") if req.path.ends_with("/daily_sleep") => Reply::json(
+            std::fs::read_to_string("tests/fixtures/daily_sleep.json")
+                .unwrap()
+                .replace(r#""score": 82"#, r#""score": 64"#),
+        ),
+        Some("Bearer bob-token") => Reply::json(r#"{"data": [], "next_token": null}"#),
+        _ => Reply::status(401, "bad token"),
+    });
+    let env = Env::new();
+    env.write_config(
+        "[accounts.alice]\ntoken = \"alice-token\"\n\n\
+         [accounts.bob]\ntoken_env = \"BOB_OURA_TOKEN\"\n\n\
+         [accounts.carol]\ntoken = \"revoked\"\n",
+    );
+    let oura = |args: &[&str]| {
+        env.command(Some(&server))
+            .env("BOB_OURA_TOKEN", "bob-token")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // --account picks the account's token over OURA_TOKEN
+    let out = stdout(&oura(&["--account", "bob", "--no-cache", "sleep", DAY]));
+    assert!(out.contains("64"));
+    assert_eq!(
+        server.requests().last().unwrap().header("authorization"),
+        Some("Bearer bob-token")
+    );
+    let out = oura(&["--account", "dave", "scores", DAY]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("available: alice, bob, carol"));
+
+    // carol's token is rejected: she is skipped, the others are compared
+    let out = oura(&["--no-cache", "scores", DAY, "--all-accounts"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("skipping account carol"));
+    let out = stdout(&out);
+    let alice = out.lines().find(|l| l.contains("alice")).unwrap();
+    let bob = out.lines().find(|l| l.contains("bob")).unwrap();
+    assert!(alice.contains("82") && alice.contains("79") && alice.contains("91"));
+    assert!(bob.contains("64") && bob.contains("--"));
+
+    let out = stdout(&oura(&[
+        "--no-cache",
+        "--format",
+        "csv",
+        "scores",
+        DAY,
+        "--all-accounts",
+    ]));
+    let lines: Vec<_> = out.lines().collect();
+    assert_eq!(
+        lines[0],
+        "account,date,sleep_score,readiness_score,activity_score,temperature_deviation"
+    );
+    assert!(lines[1].starts_with("alice,2024-02-13,82,79,91"));
+    assert!(lines[2].starts_with("bob,2024-02-13,64,,"));
+
+    let out = stdout(&oura(&[
+        "--no-cache",
+        "trend",
+        "--days",
+        "2",
+        "--end",
+        DAY,
+        "--all-accounts",
+    ]));
+    let lines: Vec<_> = out.lines().collect();
+    assert!(lines[0].contains("alice") && lines[0].contains("bob"));
+    assert_eq!(lines[1].matches("Readiness").count(), 2);
+    assert!(lines[2].contains("Mon Feb 12") && lines[2].contains("rest mode: alice"));
+    assert!(lines[3].contains("Tue Feb 13") && lines[3].contains("64"));
+
+    let out = oura(&["--no-cache", "--format", "json", "trend", "--all-accounts"]);
+    let json: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(json[0]["account"], "alice");
+    assert_eq!(json[1]["account"], "bob");
+    assert!(json[1]["days"].is_array());
+
+    // Nobody to compare
+    let env = Env::new();
+    let out = env
+        .command(Some(&server))
+        .args(["scores", "--all-accounts"])
+        .output()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&out.stderr).contains("[accounts.NAME]"));
+}
+
+#[test]
+fn independent_endpoints_are_fetched_concurrently_up_to_the_limit() {
+    use std::sync::Arc;
+
+    let in_flight = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let server = {
+        let (in_flight, peak) = (Arc::clone(&in_flight), Arc::clone(&peak));
+        MockServer::start(move |req| {
+            let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(150));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            common::fixture_reply(req)
+        })
+    };
+    let trend = |extra: &[&str]| {
+        peak.store(0, Ordering::SeqCst);
+        let args = [&["--no-cache", "trend", "--days", "3", "--end", DAY], extra].concat();
+        let out = run(&server, &args);
+        assert!(out.status.success());
+        (stdout(&out), peak.load(Ordering::SeqCst))
+    };
+
+    // Five endpoints, at most four at a time by default
+    let (out, overlapping) = trend(&[]);
+    assert!(out.contains("Tue Feb 13"));
+    assert_eq!(overlapping, 4);
+
+    let (serial, overlapping) = trend(&["--concurrency", "1"]);
+    assert_eq!(overlapping, 1);
+    assert_eq!(serial, out);
+    assert_eq!(server.requests().len(), 10);
+}